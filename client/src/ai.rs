@@ -0,0 +1,145 @@
+use std::collections::{HashSet, VecDeque};
+use ::rand::Rng;
+use shared::{Coord, Direction, PickupKind, SnekId};
+use crate::game::Game;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Difficulty {
+    // Picks any move that does not crash right away
+    Easy,
+    // Heads for the food while avoiding pockets too small to fit in
+    Medium,
+    // Searches every path a few moves ahead
+    Hard
+}
+
+pub const DIFFICULTIES: [(&str, Difficulty); 3] = [
+    ("Easy", Difficulty::Easy),
+    ("Medium", Difficulty::Medium),
+    ("Hard", Difficulty::Hard)
+];
+
+const DIRECTIONS: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+// Free space counted past this is not worth the time
+const SPACE_LIMIT: usize = 150;
+const LOOKAHEAD_DEPTH: u32 = 5;
+const DEAD_END: f32 = -1000.0;
+
+pub fn choose_direction(game: &Game, id: SnekId, difficulty: Difficulty) -> Option<Direction> {
+    // Pick the next move of an AI snek, nothing when every move crashes
+    let snek = game.sneks.get(&id)?;
+    let moves: Vec<(Direction, Coord)> = DIRECTIONS.iter()
+        .filter(|direction| !is_reverse(snek.direction, **direction))
+        .map(|direction| (*direction, step(snek.head, *direction)))
+        .filter(|(_, cell)| !game.is_blocked(*cell))
+        .collect();
+    if moves.is_empty() {
+        return None;
+    }
+
+    let scored: Vec<(Direction, f32)> = match difficulty {
+        Difficulty::Easy => {
+            // Mostly keep going straight, turning at random now and then
+            let mut rng = ::rand::thread_rng();
+            if moves.iter().any(|(direction, _)| *direction == snek.direction) && rng.gen_bool(0.8) {
+                return Some(snek.direction);
+            }
+            return Some(moves[rng.gen_range(0..moves.len())].0);
+        },
+        Difficulty::Medium => moves.iter().map(|(direction, cell)| {
+            let space = flood_fill(game, *cell, &HashSet::new());
+            let score = if space < snek.length() {
+                DEAD_END + space as f32
+            } else {
+                -(food_distance(game, *cell) as f32) - 5.0 * near_other_head(game, id, *cell) as f32
+            };
+            (*direction, score)
+        }).collect(),
+        Difficulty::Hard => moves.iter().map(|(direction, cell)| {
+            let mut visited = HashSet::from([*cell]);
+            let score = search(game, *cell, LOOKAHEAD_DEPTH, &mut visited)
+                - 5.0 * near_other_head(game, id, *cell) as f32;
+            (*direction, score)
+        }).collect()
+    };
+    scored.into_iter()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(direction, _)| direction)
+}
+
+fn search(game: &Game, cell: Coord, depth: u32, visited: &mut HashSet<Coord>) -> f32 {
+    // Score the best path of the given length from a cell. Food on the way
+    // counts more the sooner it is reached, and the path should end with room
+    // left to move
+    let food_bonus = if is_food(game, cell) { 10.0 * (depth + 1) as f32 } else { 0.0 };
+    if depth == 0 {
+        let space = flood_fill(game, cell, visited) as f32;
+        return food_bonus + space - 0.5 * food_distance(game, cell) as f32;
+    }
+    let mut best = DEAD_END;
+    for direction in DIRECTIONS {
+        let next = step(cell, direction);
+        if game.is_blocked(next) || visited.contains(&next) {
+            continue;
+        }
+        visited.insert(next);
+        best = best.max(search(game, next, depth - 1, visited));
+        visited.remove(&next);
+    }
+    food_bonus + best
+}
+
+fn flood_fill(game: &Game, start: Coord, blocked: &HashSet<Coord>) -> usize {
+    // Count the free cells reachable from a cell, up to the limit
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(cell) = queue.pop_front() {
+        if seen.len() >= SPACE_LIMIT {
+            break;
+        }
+        for direction in DIRECTIONS {
+            let next = step(cell, direction);
+            if !game.is_blocked(next) && !blocked.contains(&next) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    seen.len()
+}
+
+fn food_distance(game: &Game, cell: Coord) -> i32 {
+    // Get the number of moves to the closest food, ignoring what is in the way
+    game.pickups.iter()
+        .filter(|(_, kind)| *kind == PickupKind::Food)
+        .map(|(food, _)| (food.0 - cell.0).abs() + (food.1 - cell.1).abs())
+        .min()
+        .unwrap_or(0)
+}
+
+fn is_food(game: &Game, cell: Coord) -> bool {
+    game.pickups.iter().any(|(food, kind)| *food == cell && *kind == PickupKind::Food)
+}
+
+fn near_other_head(game: &Game, id: SnekId, cell: Coord) -> usize {
+    // Count the other sneks that could move into the same cell next tick
+    game.sneks.values()
+        .filter(|other| other.id != id)
+        .filter(|other| (other.head.0 - cell.0).abs() + (other.head.1 - cell.1).abs() == 1)
+        .count()
+}
+
+fn step(cell: Coord, direction: Direction) -> Coord {
+    match direction {
+        Direction::North => (cell.0, cell.1 - 1),
+        Direction::South => (cell.0, cell.1 + 1),
+        Direction::East => (cell.0 + 1, cell.1),
+        Direction::West => (cell.0 - 1, cell.1),
+        Direction::Invalid => cell
+    }
+}
+
+fn is_reverse(current: Direction, direction: Direction) -> bool {
+    matches!((current, direction),
+        (Direction::North, Direction::South) | (Direction::South, Direction::North) |
+        (Direction::East, Direction::West) | (Direction::West, Direction::East))
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::panic;
+use std::sync::OnceLock;
+use log::warn;
+use macroquad::audio::{load_sound_from_bytes, play_sound, PlaySoundParams, Sound};
+use macroquad::prelude::*;
+use crate::settings::ClientSettings;
+use crate::theme::Theme;
+
+const SAMPLE_RATE: u32 = 44100;
+const VOLUME_STEP: f32 = 0.1;
+const STATUS_SECONDS: f64 = 1.5;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Cue {
+    Turn,
+    Eat,
+    Death,
+    OwnDeath,
+    Countdown,
+    Go,
+    Win,
+    Tie,
+    Loss
+}
+
+impl Cue {
+    fn notes(&self) -> &'static [(f32, f32)] {
+        // Get the notes of the cue, as frequency in Hz and length in seconds
+        match self {
+            Cue::Turn => &[(880.0, 0.03)],
+            Cue::Eat => &[(660.0, 0.05), (990.0, 0.07)],
+            Cue::Death => &[(330.0, 0.08), (220.0, 0.12)],
+            Cue::OwnDeath => &[(440.0, 0.1), (330.0, 0.1), (220.0, 0.1), (110.0, 0.25)],
+            Cue::Countdown => &[(440.0, 0.12)],
+            Cue::Go => &[(880.0, 0.25)],
+            Cue::Win => &[(523.0, 0.12), (659.0, 0.12), (784.0, 0.12), (1047.0, 0.3)],
+            Cue::Tie => &[(523.0, 0.15), (523.0, 0.3)],
+            Cue::Loss => &[(392.0, 0.15), (370.0, 0.15), (349.0, 0.15), (330.0, 0.4)]
+        }
+    }
+
+    fn loudness(&self) -> f32 {
+        // Frequent cues are kept quiet
+        match self {
+            Cue::Turn => 0.3,
+            _ => 1.0
+        }
+    }
+}
+
+const CUES: [Cue; 9] = [
+    Cue::Turn, Cue::Eat, Cue::Death, Cue::OwnDeath, Cue::Countdown, Cue::Go, Cue::Win, Cue::Tie, Cue::Loss
+];
+
+pub struct Audio {
+    sounds: HashMap<Cue, Sound>,
+    changed_at: f64
+}
+
+impl Audio {
+    pub async fn load() -> Audio {
+        // Synthesize every cue. Without a sound device there are no sounds,
+        // so playing does nothing
+        let mut sounds = HashMap::new();
+        if !has_sound_device() {
+            warn!("No sound device found, playing without sound");
+            return Audio { sounds, changed_at: -10.0 };
+        }
+        for cue in CUES {
+            match load_sound_from_bytes(&synthesize(cue.notes())).await {
+                Ok(sound) => { sounds.insert(cue, sound); },
+                Err(error) => warn!("Could not load a sound: {:?}", error)
+            }
+        }
+        Audio { sounds, changed_at: -10.0 }
+    }
+
+    pub fn play(&self, cue: Cue, settings: &ClientSettings) {
+        // Play a cue at the chosen volume
+        if settings.muted {
+            return;
+        }
+        if let Some(sound) = self.sounds.get(&cue) {
+            play_sound(*sound, PlaySoundParams { looped: false, volume: settings.volume * cue.loudness() });
+        }
+    }
+
+    pub fn handle_events(&mut self, settings: &mut ClientSettings) {
+        // M mutes, [ and ] change the volume. Changes are saved right away
+        if is_key_pressed(KeyCode::M) {
+            settings.muted = !settings.muted;
+        } else if is_key_pressed(KeyCode::LeftBracket) {
+            settings.volume = (settings.volume - VOLUME_STEP).max(0.0);
+        } else if is_key_pressed(KeyCode::RightBracket) {
+            settings.volume = (settings.volume + VOLUME_STEP).min(1.0);
+        } else {
+            return;
+        }
+        settings.save();
+        self.changed_at = get_time();
+    }
+
+    pub fn draw_status(&self, settings: &ClientSettings, theme: &Theme) {
+        // Show the volume for a moment after it changes
+        if get_time() - self.changed_at > STATUS_SECONDS {
+            return;
+        }
+        let text = if settings.muted {
+            "Muted".to_owned()
+        } else {
+            format!("Volume {}%", (settings.volume * 100.0).round())
+        };
+        draw_text(&text, 10.0, screen_height() - 10.0, 24.0, theme.dim_text);
+    }
+}
+
+pub fn has_sound_device() -> bool {
+    // Check once if a sound device can be opened
+    static HAS_DEVICE: OnceLock<bool> = OnceLock::new();
+    *HAS_DEVICE.get_or_init(open_device)
+}
+
+pub fn silence_mixer() {
+    // Without a sound device the mixer thread of macroquad panics as the
+    // window opens. Audio::load reports the missing device instead
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !info.location().is_some_and(|location| location.file().contains("quad-snd")) {
+            default_hook(info);
+        }
+    }));
+}
+
+#[cfg(target_os = "linux")]
+fn open_device() -> bool {
+    // Try the ALSA devices the mixer opens, and let go of the one that opened
+    use quad_alsa_sys as alsa;
+    ["default\0", "pipewire\0"].iter().any(|device| unsafe {
+        let mut handle = std::ptr::null_mut();
+        let opened = alsa::snd_pcm_open(&mut handle, device.as_ptr() as _, alsa::SND_PCM_STREAM_PLAYBACK, 0) >= 0;
+        if opened {
+            alsa::snd_pcm_close(handle);
+        }
+        opened
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_device() -> bool {
+    // Only the ALSA mixer is probed, the other platforms are assumed to have a device
+    true
+}
+
+fn synthesize(notes: &[(f32, f32)]) -> Vec<u8> {
+    // Render the notes as a mono 16 bit WAV file. Each note fades in and out
+    // quickly so they do not click
+    let mut samples: Vec<i16> = Vec::new();
+    for &(frequency, seconds) in notes {
+        let count = (seconds * SAMPLE_RATE as f32) as usize;
+        let fade = (count / 10).max(1);
+        for i in 0..count {
+            let envelope = (i.min(count - i) as f32 / fade as f32).min(1.0);
+            let phase = i as f32 * frequency / SAMPLE_RATE as f32;
+            let value = (phase * std::f32::consts::TAU).sin() * envelope * 0.5;
+            samples.push((value * i16::MAX as f32) as i16);
+        }
+    }
+
+    let data_size = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_size).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(SAMPLE_RATE.to_le_bytes());
+    wav.extend((SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend(sample.to_le_bytes());
+    }
+    wav
+}
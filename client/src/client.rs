@@ -1,108 +1,210 @@
-use std::net::SocketAddr;
-use std::io;
-use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::time::Instant;
 
+use clap::Parser;
+use log::{debug, info, trace, warn};
 use laminar::{ErrorKind, Packet, Socket, SocketEvent};
-use macroquad::prelude::{next_frame, get_time, clear_background, Conf, BLACK};
-use shared::{MessageType, MAGIC_BYTE, Direction, GameResult};
+use macroquad::prelude::{
+    next_frame, get_time, clear_background, is_key_pressed, set_fullscreen, Color, Conf, KeyCode
+};
+use shared::{Delivery, MessageType, MatchSettings, MAGIC_BYTE, NO_TEAM, Direction, GameResult};
+use crate::audio::{self, Audio, Cue};
+use crate::config::{self, Args};
+use crate::controls::Gamepads;
 use crate::game::Game;
+use crate::hud;
+use crate::local::{LocalMatch, LocalOptions};
+use crate::menu::{self, ControlsMenu, MainMenu, MenuAction};
+use crate::settings::ClientSettings;
+use crate::theme::Theme;
 
 const WINDOW_WIDTH: i32 = 800;
 const WINDOW_HEIGHT: i32 = 800;
 
 pub fn client() {
+    // Check for a sound device before the window opens and starts the mixer
+    if !audio::has_sound_device() {
+        audio::silence_mixer();
+    }
     main();
 }
 
 fn send_packet(message_type: MessageType, payload: Vec<u8>, address: SocketAddr, sender: &mut Socket) {
-    // if message_type != MessageType::Heartbeat {
-    //     println!("sending packet with payload {:?}", payload);
-    // }
     // Client sends a packet to server
+    trace!(kind = message_type as u8, bytes = payload.len(); "Sending packet");
     let mut actual_payload = vec![MAGIC_BYTE, message_type as u8];
     actual_payload.extend(payload.iter());
-    sender.send(Packet::reliable_sequenced(address, actual_payload, Some(0))).unwrap();
+    let (delivery, stream) = message_type.delivery();
+    let packet = match delivery {
+        Delivery::ReliableOrdered => Packet::reliable_ordered(address, actual_payload, Some(stream as u8)),
+        Delivery::UnreliableSequenced => Packet::unreliable_sequenced(address, actual_payload, Some(stream as u8)),
+        Delivery::Unreliable => Packet::unreliable(address, actual_payload)
+    };
+    sender.send(packet).unwrap();
     sender.manual_poll(Instant::now());
 }
 
-fn handle_packet(packet: Packet, game: &mut Game) -> bool {
+fn handle_packet(packet: Packet, game: &mut Game, lobby: &mut Vec<String>) {
     // Client receives a packet from server
     let payload = packet.payload();
-    // println!("payload: {:?}", payload);
-    let magic_byte = payload[0];
-    if magic_byte != MAGIC_BYTE {
-        return false;
+    if payload.len() < 2 || payload[0] != MAGIC_BYTE {
+        warn!(bytes = payload.len(); "Ignoring a packet that is not from a Snek server");
+        return;
     }
     let message_type = payload[1];
     let received_data = &packet.payload()[2..];
+    trace!(kind = message_type, bytes = received_data.len(); "Received packet");
     // Handle different events recieved
     match message_type {
         // Assign the snek ids
         x if x == MessageType::AssignIdEvent as u8 => {
             let assigned_id = received_data[0];
-            println!("Assigned ID {}", assigned_id);
+            info!(snek = assigned_id; "Joined a match");
             game.spawn_snek(assigned_id).unwrap();
             game.set_my_snek_id(assigned_id);
-            return false;
         },
-        // Broadcast the current sneks playing
+        // Broadcast the current sneks playing, with their colors and names
         x if x == MessageType::BroadcastIdsEvent as u8 => {
-            println!("IDs: {:?}", received_data);
-            for &id in received_data {
+            let mut rest = received_data;
+            while rest.len() >= 5 {
+                let id = rest[0];
+                let color = Color::from_rgba(rest[1], rest[2], rest[3], 255);
+                let name_end = (5 + rest[4] as usize).min(rest.len());
+                let name = String::from_utf8_lossy(&rest[5..name_end]).into_owned();
+                rest = &rest[name_end..];
+
+                debug!(snek = id, name = name.as_str(); "Player in the match");
+                game.add_player(id, name, color);
                 if id != game.get_my_snek_id() {
                     game.spawn_snek(id).unwrap();
                 }
             }
-            return false;
         },
         // Start the game
         x if x == MessageType::StartEvent as u8 => {
-            println!("Starting!");
-            game.start_game();
-            return true;
+            let settings = MatchSettings::decode(received_data).unwrap_or_else(|| {
+                warn!(bytes = received_data.len(); "Invalid match settings, playing with the defaults");
+                MatchSettings::default()
+            });
+            info!(mode = settings.mode as u8, players = settings.player_count, tick_millis = settings.tick_millis; "Match starting");
+            game.start_game(settings);
+        },
+        // Count down to the first tick
+        x if x == MessageType::CountdownEvent as u8 => {
+            if received_data.is_empty() {
+                warn!("Ignoring an empty countdown");
+                return;
+            }
+            debug!(seconds = received_data[0]; "Countdown");
+            game.set_countdown(received_data[0]);
         },
         // Update game from snek moves
         x if x == MessageType::MoveEvent as u8 => {
-            // println!("Moving!");
-            for i in (0..received_data.len()).step_by(2) {
-                let snek_id = received_data[i];
-                let direction = match received_data[i + 1] {
-                    x if x == Direction::North as u8 => Some(Direction::North),
-                    x if x == Direction::South as u8 => Some(Direction::South),
-                    x if x == Direction::East as u8 => Some(Direction::East),
-                    x if x == Direction::West as u8 => Some(Direction::West),
-                    _ => None
-                };
-                // println!("Snek {} should be going {}", snek_id, direction.unwrap() as u8);
-                game.move_snek(snek_id, direction.unwrap());
+            // Moves can arrive before the start of the game, the next packet
+            // sends them again
+            if !game.has_started() || received_data.len() < 4 {
+                return;
+            }
+            // Every tick is played once, in order, and the packet repeats the
+            // ticks sent before in case those were lost
+            let mut tick = u32::from_be_bytes([received_data[0], received_data[1], received_data[2], received_data[3]]);
+            let mut rest = &received_data[4..];
+            while !rest.is_empty() {
+                let move_count = rest[0] as usize;
+                if rest.len() < 1 + 2 * move_count {
+                    warn!(tick = tick; "Ignoring the rest of a truncated move packet");
+                    return;
+                }
+                let mut moves = Vec::new();
+                for pair in rest[1..1 + 2 * move_count].chunks_exact(2) {
+                    let direction = match pair[1] {
+                        x if x == Direction::North as u8 => Direction::North,
+                        x if x == Direction::South as u8 => Direction::South,
+                        x if x == Direction::East as u8 => Direction::East,
+                        x if x == Direction::West as u8 => Direction::West,
+                        _ => continue
+                    };
+                    moves.push((pair[0], direction));
+                }
+                if tick == game.get_confirmed_tick() + 1 {
+                    game.confirm_tick(&moves);
+                }
+                tick += 1;
+                rest = &rest[1 + 2 * move_count..];
             }
-            return true;
         },
         // End the game and broadcast the result
         x if x == MessageType::EndEvent as u8 => {
-            println!("Game ended!");
-            match received_data[0] {
+            if received_data.len() < 3 {
+                warn!(bytes = received_data.len(); "Ignoring a truncated match result");
+                return;
+            }
+            game.push_cue(match received_data[0] {
+                x if x == GameResult::Win as u8 => Cue::Win,
+                x if x == GameResult::Tie as u8 => Cue::Tie,
+                _ => Cue::Loss
+            });
+            let result = match received_data[0] {
                 x if x == GameResult::Win as u8 => {
-                    println!("You won!");
+                    if received_data[2] != NO_TEAM {
+                        "Your team won!".to_owned()
+                    } else {
+                        "You won!".to_owned()
+                    }
                 },
                 x if x == GameResult::Tie as u8 => {
-                    println!("You tied!");
-                },
-                x if x == GameResult::Loss as u8 => {
-                    println!("You lost to snek {}!", received_data[1]);
+                    "You tied!".to_owned()
                 },
-                _ => {}
-            }
+                _ => {
+                    if received_data[2] != NO_TEAM {
+                        format!("You lost to team {}!", received_data[2])
+                    } else {
+                        format!("You lost to {}!", game.get_player_name(received_data[1]))
+                    }
+                }
+            };
+            info!(result = result.as_str(); "Match ended");
+            game.set_result(result);
             game.end_game();
-            return false;
+        },
+        // Update the players waiting for a match
+        x if x == MessageType::LobbyEvent as u8 => {
+            lobby.clear();
+            let mut rest = received_data;
+            while !rest.is_empty() {
+                let name_end = (1 + rest[0] as usize).min(rest.len());
+                lobby.push(String::from_utf8_lossy(&rest[1..name_end]).into_owned());
+                rest = &rest[name_end..];
+            }
+        },
+        // Update the clock of a timed match, when it runs out the last tick
+        // to play comes with it
+        x if x == MessageType::TimeEvent as u8 => {
+            if received_data.len() < 2 {
+                warn!(bytes = received_data.len(); "Ignoring a truncated time update");
+                return;
+            }
+            let remaining = u16::from_be_bytes([received_data[0], received_data[1]]);
+            if remaining == 0 {
+                if received_data.len() < 6 {
+                    warn!(bytes = received_data.len(); "Ignoring a time update without the last tick");
+                    return;
+                }
+                game.set_final_tick(u32::from_be_bytes([received_data[2], received_data[3], received_data[4], received_data[5]]));
+            }
+            game.set_remaining_time(remaining);
         },
         // Send heartbeat
         x if x == MessageType::Heartbeat as u8 => {
-            // println!("Heartbeat!");
-            return false;
+            // Heartbeats echoing our send time measure the ping
+            if received_data.len() != 8 {
+                return;
+            }
+            let mut sent_time = [0; 8];
+            sent_time.copy_from_slice(received_data);
+            game.set_ping(get_time() - f64::from_be_bytes(sent_time));
         }
-        _ => { return false; }
+        _ => warn!(kind = message_type; "Ignoring a packet of unknown kind")
     }
 }
 
@@ -110,119 +212,289 @@ fn window_conf() -> Conf {
     // Configures the client's window
     Conf {
         window_title: "Snek".to_owned(),
-        window_resizable: false,
+        window_resizable: true,
         window_width: WINDOW_WIDTH,
         window_height: WINDOW_HEIGHT,
         ..Default::default()
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() -> Result<(), ErrorKind> {
-    let mut port = 8432;
-    let mut addr: String;
-    // Bind the client to the server socket 
-    let mut socket: Socket;
-    loop {
-        addr = format!("{}:{}", "127.0.0.1", port);
-        match Socket::bind(&addr) {
-            Ok(s) => {
-                socket = s;
-                break;
-            },
-            Err(_) => {
-                port += 1;
-            }
-        };
-    }
-    println!("Binded to IP {}", &addr);
-
-    let stdin = io::stdin();
+fn new_game(smooth: bool, theme: &Theme) -> Game {
+    // Create an empty board for the next match
+    let mut game = Game::new(
+        WINDOW_WIDTH,  WINDOW_HEIGHT,
+        20, 35, 35
+    );
+    game.set_smooth(smooth);
+    game.set_theme(theme.clone());
+    game
+}
 
-    print!("Enter server IP:port (127.0.0.1:8080): ");
-    io::stdout().flush().unwrap();
+struct Connection {
+    socket: Socket,
+    server_address: SocketAddr,
+    last_send_move_time: f64,
+    last_sent_tick: u32,
+    last_heartbeat_time: f64,
+    heartbeat_sequence: u32
+}
 
-    let mut server_address_string = String::new();
-    stdin.read_line(&mut server_address_string)?;
-    if server_address_string.ends_with('\n') {
-        server_address_string.pop();
-        if server_address_string.ends_with('\r') {
-            server_address_string.pop();
-        }
+impl Connection {
+    fn open(server_address: SocketAddr, bind: Option<IpAddr>) -> Result<Connection, ErrorKind> {
+        // Bind the client to a port picked by the OS, on the server's address
+        // family unless a local address was given
+        let bind_ip = bind.unwrap_or(match server_address {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        });
+        let socket = Socket::bind(SocketAddr::new(bind_ip, 0))?;
+        info!(address:% = socket.local_addr()?; "Bound the client socket");
+        Ok(Connection { socket, server_address, last_send_move_time: -10.0, last_sent_tick: 0, last_heartbeat_time: -10.0, heartbeat_sequence: 0 })
     }
 
-    // Tell server to add the client
-    let server_address = match server_address_string.parse::<SocketAddr>() {
-        Ok(address) => address,
-        Err(_) => "127.0.0.1:8080".parse::<SocketAddr>().unwrap()
-    };
-    send_packet(MessageType::JoinEvent, vec![], server_address, &mut socket);
-    socket.manual_poll(Instant::now());
-    println!("Attempting to join server {}...", server_address);
+    fn join(&mut self, name: &str, color: [u8; 3]) {
+        // Tell server to add the client
+        let mut join_payload = color.to_vec();
+        join_payload.extend(name.as_bytes());
+        send_packet(MessageType::JoinEvent, join_payload, self.server_address, &mut self.socket);
+        info!(server:% = self.server_address; "Joining the queue");
+    }
 
-    let mut game = Game::new(
-        WINDOW_WIDTH,  WINDOW_HEIGHT,
-        20, 35, 35
-    );
+    fn leave(&mut self) {
+        // Tell server to take the client out of the queue
+        info!(server:% = self.server_address; "Leaving the queue");
+        send_packet(MessageType::LeaveEvent, vec![], self.server_address, &mut self.socket);
+    }
 
-    let mut last_send_move_time: f64 = -10.0;
-    let mut last_heartbeat_time: f64 = -10.0;
+    fn receive(&mut self, game: &mut Game, lobby: &mut Vec<String>) {
+        // Handle every packet that arrived from the server since the last frame
+        self.socket.manual_poll(Instant::now());
 
-    loop {
-        socket.manual_poll(Instant::now());
-
-        let mut should_update = false;
-        match socket.recv() {
-            Some(SocketEvent::Packet(packet)) => {
-                if packet.addr() == server_address {
-                    should_update = handle_packet(packet, &mut game);
-                    // send_packet(MessageType::Heartbeat, vec![], server_address, 
-                    //     StreamId::Heartbeat as u8, &mut socket);
+        while let Some(event) = self.socket.recv() {
+            if let SocketEvent::Packet(packet) = event {
+                if packet.addr() == self.server_address {
+                    handle_packet(packet, game, lobby);
                 }
-            },
-            _ => {}
+            }
         }
-        // send_packet(MessageType::DeathEvent, vec![], server_address, StreamId::Heartbeat as u8, &mut socket);
-        // Handles the game state 
+    }
+
+    fn update(&mut self, game: &mut Game) {
+        // Handles the game state
+        let server_address = self.server_address;
         if game.has_started() {
             let my_id = game.get_my_snek_id();
-            if !game.is_alive(my_id) {
+            if game.has_final_standings() {
+                // The server stopped the clock and every tick has been played,
+                // report the final standings
+                send_packet(MessageType::ScoreEvent, game.get_scores(), server_address,
+                    &mut self.socket);
+                game.end_game();
+            } else if !game.is_alive(my_id) {
                 let mut payload = vec![];
                 for snek_id in game.get_all_snek_ids() {
                     payload.push(snek_id);
                 }
-                send_packet(MessageType::DeathEvent, payload, server_address, 
-                    &mut socket);
+                send_packet(MessageType::DeathEvent, payload, server_address,
+                    &mut self.socket);
                 game.end_game();
             } else {
-                let time_passed = (get_time() - last_send_move_time) >= 0.03;
+                let time_passed = (get_time() - self.last_send_move_time) >= 0.03;
                 if time_passed {
-                    // println!("Updating movement!");
+                    // The move goes out when it changes and with every confirmed
+                    // tick, so a lost one is soon replaced
                     let direction = game.get_snek_direction(my_id);
-                    if game.get_previous_snek_direction(my_id) != direction {
-                        println!("Updating movement!");
-                        send_packet(MessageType::MoveEvent, vec![my_id, direction as u8], server_address, 
-                            &mut socket);
+                    let confirmed_tick = game.get_confirmed_tick();
+                    if game.get_previous_snek_direction(my_id) != direction || confirmed_tick != self.last_sent_tick {
+                        let mut payload = vec![my_id, direction as u8];
+                        payload.extend(confirmed_tick.to_be_bytes().iter());
+                        send_packet(MessageType::MoveEvent, payload, server_address, &mut self.socket);
                         game.set_previous_snek_direction(my_id, direction);
+                        self.last_sent_tick = confirmed_tick;
                     }
-                    last_send_move_time = get_time();
+                    self.last_send_move_time = get_time();
                 }
             }
         }
         // Send heartbeat if no event has occurred during specified period to prevent timeout
-        let time_passed = (get_time() - last_heartbeat_time) >= 1.0;
+        let time_passed = (get_time() - self.last_heartbeat_time) >= 1.0;
         if time_passed {
-            send_packet(MessageType::Heartbeat, vec![], server_address, 
-                &mut socket);
-            last_heartbeat_time = get_time();
+            // Heartbeats are numbered and carry the ping, so the server can
+            // report the packet loss and round trip time of every client
+            let mut payload = get_time().to_be_bytes().to_vec();
+            payload.extend(self.heartbeat_sequence.to_be_bytes().iter());
+            let rtt_millis = game.get_ping().map_or(u16::MAX, |ping| (ping * 1000.0).min(u16::MAX as f64 - 1.0) as u16);
+            payload.extend(rtt_millis.to_be_bytes().iter());
+            send_packet(MessageType::Heartbeat, payload, server_address, &mut self.socket);
+            self.heartbeat_sequence += 1;
+            self.last_heartbeat_time = get_time();
         }
+    }
+}
+
+#[derive(PartialEq)]
+enum Screen {
+    MainMenu,
+    Controls,
+    LocalSetup,
+    Lobby,
+    Playing,
+    Ended
+}
+
+#[macroquad::main(window_conf)]
+async fn main() -> Result<(), ErrorKind> {
+    let args = Args::parse();
+    shared::logging::init(args.log.as_deref(), args.log_json);
+    info!("Starting client");
+    let server_text = args.server.map(|address| address.to_string()).unwrap_or("127.0.0.1:8080".to_owned());
+    let mut menu = MainMenu::new(server_text, args.name.clone(), args.color);
 
-        clear_background(BLACK);
-        game.update(should_update);
-        game.handle_events();
+    let mut settings = ClientSettings::load();
+    let theme_name = args.theme.clone().unwrap_or(settings.theme.clone());
+    let theme = Theme::load(&theme_name, &settings.colors).unwrap_or_else(|error| {
+        warn!("{}, using the classic theme", error);
+        Theme::classic()
+    });
 
-        // send_packet(MessageType::Heartbeat, vec![], server_address, StreamId::Heartbeat as u8, &mut socket);
+    let mut game = new_game(args.smooth, &theme);
+    let mut lobby: Vec<String> = Vec::new();
+    let mut connection: Option<Connection> = None;
+    let mut local: Option<LocalMatch> = None;
+    let mut local_options = LocalOptions::new();
+    let mut screen = Screen::MainMenu;
+    let mut fullscreen = false;
+    let mut audio = Audio::load().await;
+    let mut controls_menu = ControlsMenu::new();
+    let mut gamepads = Gamepads::new();
+
+    // Skip the menu when the server was given on the command line
+    if let Some(server_address) = args.server {
+        let mut new_connection = Connection::open(server_address, args.bind)?;
+        new_connection.join(&menu.name, menu.color);
+        connection = Some(new_connection);
+        screen = Screen::Lobby;
+    }
+
+    loop {
+        if let Some(connection) = connection.as_mut() {
+            connection.receive(&mut game, &mut lobby);
+            connection.update(&mut game);
+        }
+        if let Some(local) = local.as_mut() {
+            local.update(&mut game);
+        }
+        if screen == Screen::Lobby && game.has_started() {
+            screen = Screen::Playing;
+        }
+        if screen == Screen::Playing && game.get_result().is_some() {
+            screen = Screen::Ended;
+        }
+
+        // F11 toggles fullscreen, everything is laid out for the window size each frame
+        if is_key_pressed(KeyCode::F11) {
+            fullscreen = !fullscreen;
+            set_fullscreen(fullscreen);
+        }
+
+        // Sound keys are left alone while typing in the menu or binding keys
+        if screen != Screen::MainMenu && screen != Screen::Controls {
+            audio.handle_events(&mut settings);
+        }
+
+        clear_background(theme.background);
+        match screen {
+            Screen::MainMenu => match menu.draw(&theme) {
+                MenuAction::Play => {
+                    let opened = config::parse_server_address(&menu.server_address)
+                        .and_then(|address| Connection::open(address, args.bind)
+                            .map_err(|error| format!("Could not connect: {:?}", error)));
+                    match opened {
+                        Ok(mut new_connection) => {
+                            new_connection.join(&menu.name, menu.color);
+                            connection = Some(new_connection);
+                            local = None;
+                            menu.error = None;
+                            game = new_game(args.smooth, &theme);
+                            lobby.clear();
+                            screen = Screen::Lobby;
+                        },
+                        Err(error) => menu.error = Some(error)
+                    }
+                },
+                MenuAction::Local => screen = Screen::LocalSetup,
+                MenuAction::Controls => screen = Screen::Controls,
+                MenuAction::Quit => break,
+                _ => {}
+            },
+            Screen::LocalSetup => match menu::draw_local_setup(&mut local_options, &settings.controls, &settings.local_controls, &theme) {
+                MenuAction::Play => {
+                    game = new_game(args.smooth, &theme);
+                    local = Some(LocalMatch::start(local_options, &mut game));
+                    screen = Screen::Playing;
+                },
+                MenuAction::Leave => screen = Screen::MainMenu,
+                _ => {}
+            },
+            Screen::Controls => {
+                if controls_menu.draw(&mut settings.controls, &theme) == MenuAction::Leave {
+                    settings.save();
+                    screen = Screen::MainMenu;
+                }
+            },
+            Screen::Lobby => {
+                if menu::draw_lobby(&menu.server_address, &lobby, &theme) == MenuAction::Leave {
+                    if let Some(connection) = connection.as_mut() {
+                        connection.leave();
+                    }
+                    connection = None;
+                    screen = Screen::MainMenu;
+                }
+            },
+            Screen::Playing => {
+                game.update();
+                hud::draw_minimap(&game);
+                hud::draw_hud(&game);
+                let direction = settings.controls.direction().or(gamepads.direction());
+                match local.as_mut() {
+                    // Everyone at the keyboard plays their own snek, a single
+                    // player turns theirs like online
+                    Some(local) => {
+                        local.handle_events(&settings.local_controls, direction);
+                        game.handle_events(direction.filter(|_| local.options.is_solo()));
+                    },
+                    None => game.handle_events(direction)
+                }
+            },
+            Screen::Ended => {
+                game.update();
+                hud::draw_minimap(&game);
+                hud::draw_hud(&game);
+                match menu::draw_end_screen(&game.get_result().unwrap_or_default(), &theme) {
+                    MenuAction::Rematch => {
+                        game = new_game(args.smooth, &theme);
+                        lobby.clear();
+                        if let Some(connection) = connection.as_mut() {
+                            connection.join(&menu.name, menu.color);
+                        }
+                        screen = Screen::Lobby;
+                        if let Some(previous) = local.as_ref() {
+                            local = Some(LocalMatch::start(previous.options, &mut game));
+                            screen = Screen::Playing;
+                        }
+                    },
+                    MenuAction::Quit => break,
+                    _ => {}
+                }
+            }
+        }
+
+        for cue in game.take_cues() {
+            audio.play(cue, &settings);
+        }
+        audio.draw_status(&settings, &theme);
 
         next_frame().await;
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use clap::Parser;
+use shared::{DEFAULT_COLOR, MAX_NAME_LENGTH};
+
+pub const NAMED_COLORS: [(&str, [u8; 3]); 8] = [
+    ("red", DEFAULT_COLOR),
+    ("orange", [255, 161, 0]),
+    ("yellow", [253, 249, 0]),
+    ("green", [0, 228, 48]),
+    ("blue", [0, 121, 241]),
+    ("purple", [200, 122, 255]),
+    ("pink", [255, 109, 194]),
+    ("white", [255, 255, 255])
+];
+
+// Everything needed to join a game without touching the terminal
+#[derive(Parser)]
+#[command(name = "snek-client", about = "Plays multiplayer Snek")]
+pub struct Args {
+    /// Server to join, as IP:port or hostname:port. Skips the main menu when given
+    #[arg(short, long, value_parser = parse_server_address)]
+    pub server: Option<SocketAddr>,
+    /// Local address to bind, the port is picked by the OS
+    #[arg(short, long, value_parser = parse_bind_address)]
+    pub bind: Option<IpAddr>,
+    /// Name shown to the other players and used for your rating
+    #[arg(short, long, default_value = "", value_parser = parse_name)]
+    pub name: String,
+    /// Color of your snek, by name (red, blue, ...) or as #rrggbb
+    #[arg(short, long, default_value = "red", value_parser = parse_color)]
+    pub color: [u8; 3],
+    /// Draw the sneks sliding smoothly between cells instead of jumping
+    #[arg(long)]
+    pub smooth: bool,
+    /// Color theme: classic, high-contrast or colorblind. Overrides the settings file
+    #[arg(short, long)]
+    pub theme: Option<String>,
+    /// Levels to log at, such as "debug" or "info,snek_client::client=trace".
+    /// Overrides the SNEK_LOG environment variable
+    #[arg(long)]
+    pub log: Option<String>,
+    /// Log JSON lines instead of text
+    #[arg(long)]
+    pub log_json: bool
+}
+
+pub fn parse_server_address(text: &str) -> Result<SocketAddr, String> {
+    // Resolve the server address, which may use a hostname. Hostnames such as
+    // localhost often resolve to IPv6 first, but the server listens on IPv4
+    // unless told otherwise, so an IPv4 address is picked when there is one
+    let addresses: Vec<SocketAddr> = text.trim().to_socket_addrs()
+        .map_err(|error| format!("Could not resolve {}: {}", text, error))?
+        .collect();
+    addresses.iter().find(|address| address.is_ipv4()).or(addresses.first())
+        .copied()
+        .ok_or(format!("No address found for {}", text))
+}
+
+fn parse_bind_address(text: &str) -> Result<IpAddr, String> {
+    // Parse the local address, IPv6 addresses may be written in brackets
+    text.trim().trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()
+        .map_err(|_| format!("Invalid address to bind: {}", text))
+}
+
+fn parse_name(text: &str) -> Result<String, String> {
+    // Names are sent in a single packet, so keep them short
+    let name = text.trim();
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("Names can be at most {} characters", MAX_NAME_LENGTH));
+    }
+    Ok(name.to_owned())
+}
+
+pub fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    // Parse a color name or a #rrggbb hex code
+    let name = text.trim().to_lowercase();
+    if let Some((_, color)) = NAMED_COLORS.iter().find(|(color_name, _)| *color_name == name) {
+        return Ok(*color);
+    }
+    let hex = text.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("Unknown color: {}", text));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16)
+        .map_err(|_| format!("Unknown color: {}", text));
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
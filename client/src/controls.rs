@@ -0,0 +1,157 @@
+use gilrs::{Axis, Button, Gilrs};
+use log::warn;
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use shared::Direction;
+
+// Keys that can be bound, by the name used in the settings file. Keys used
+// for zoom, sound and fullscreen are left out
+const KEY_NAMES: [(&str, KeyCode); 54] = [
+    ("Up", KeyCode::Up), ("Down", KeyCode::Down), ("Left", KeyCode::Left), ("Right", KeyCode::Right),
+    ("A", KeyCode::A), ("B", KeyCode::B), ("C", KeyCode::C), ("D", KeyCode::D), ("E", KeyCode::E),
+    ("F", KeyCode::F), ("G", KeyCode::G), ("H", KeyCode::H), ("I", KeyCode::I), ("J", KeyCode::J),
+    ("K", KeyCode::K), ("L", KeyCode::L), ("N", KeyCode::N), ("O", KeyCode::O), ("P", KeyCode::P),
+    ("Q", KeyCode::Q), ("R", KeyCode::R), ("S", KeyCode::S), ("T", KeyCode::T), ("U", KeyCode::U),
+    ("V", KeyCode::V), ("W", KeyCode::W), ("X", KeyCode::X), ("Y", KeyCode::Y), ("Z", KeyCode::Z),
+    ("0", KeyCode::Key0), ("1", KeyCode::Key1), ("2", KeyCode::Key2), ("3", KeyCode::Key3), ("4", KeyCode::Key4),
+    ("5", KeyCode::Key5), ("6", KeyCode::Key6), ("7", KeyCode::Key7), ("8", KeyCode::Key8), ("9", KeyCode::Key9),
+    ("Numpad0", KeyCode::Kp0), ("Numpad1", KeyCode::Kp1), ("Numpad2", KeyCode::Kp2), ("Numpad3", KeyCode::Kp3),
+    ("Numpad4", KeyCode::Kp4), ("Numpad5", KeyCode::Kp5), ("Numpad6", KeyCode::Kp6), ("Numpad7", KeyCode::Kp7),
+    ("Numpad8", KeyCode::Kp8), ("Numpad9", KeyCode::Kp9), ("Space", KeyCode::Space), ("Semicolon", KeyCode::Semicolon),
+    ("Comma", KeyCode::Comma), ("Period", KeyCode::Period), ("Slash", KeyCode::Slash)
+];
+
+pub const PRESETS: [(&str, [&str; 4]); 3] = [
+    ("Arrows", ["Up", "Down", "Left", "Right"]),
+    ("WASD", ["W", "S", "A", "D"]),
+    ("Vim", ["K", "J", "H", "L"])
+];
+
+// Keys of each player sharing the keyboard in a local match
+pub const LOCAL_PRESETS: [[&str; 4]; 4] = [
+    ["Up", "Down", "Left", "Right"],
+    ["W", "S", "A", "D"],
+    ["I", "K", "J", "L"],
+    ["Numpad8", "Numpad5", "Numpad4", "Numpad6"]
+];
+
+// How far a stick has to be pushed before it turns the snek
+const STICK_DEADZONE: f32 = 0.5;
+
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    // Get the settings file name of a key
+    KEY_NAMES.iter().find(|(_, code)| *code == key).map(|(name, _)| *name)
+}
+
+fn key_code(name: &str) -> Option<KeyCode> {
+    // Get a key from its settings file name
+    KEY_NAMES.iter().find(|(key_name, _)| key_name.eq_ignore_ascii_case(name)).map(|(_, code)| *code)
+}
+
+// The key for each direction, as stored in the settings file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings::preset(PRESETS[0].1)
+    }
+}
+
+impl KeyBindings {
+    pub fn preset(keys: [&str; 4]) -> KeyBindings {
+        // Bind the keys of a preset, in up, down, left, right order
+        KeyBindings {
+            up: keys[0].to_owned(),
+            down: keys[1].to_owned(),
+            left: keys[2].to_owned(),
+            right: keys[3].to_owned()
+        }
+    }
+
+    pub fn local_defaults() -> Vec<KeyBindings> {
+        // Get the keys of every local player
+        LOCAL_PRESETS.iter().map(|keys| KeyBindings::preset(*keys)).collect()
+    }
+
+    pub fn describe(&self) -> String {
+        // List the keys in up, down, left, right order
+        format!("{} {} {} {}", self.up, self.down, self.left, self.right)
+    }
+
+    pub fn keys_mut(&mut self) -> [(Direction, &mut String); 4] {
+        // Get the binding of each direction
+        [
+            (Direction::North, &mut self.up),
+            (Direction::South, &mut self.down),
+            (Direction::West, &mut self.left),
+            (Direction::East, &mut self.right)
+        ]
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        // Get the direction of the bound key held down, unknown names are ignored
+        let bindings = [
+            (&self.up, Direction::North),
+            (&self.down, Direction::South),
+            (&self.right, Direction::East),
+            (&self.left, Direction::West)
+        ];
+        bindings.iter()
+            .find(|(name, _)| key_code(name).is_some_and(is_key_down))
+            .map(|(_, direction)| *direction)
+    }
+}
+
+pub struct Gamepads {
+    gilrs: Option<Gilrs>
+}
+
+impl Gamepads {
+    pub fn new() -> Gamepads {
+        // Gamepads are optional, the game plays on without them
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(error) => {
+                warn!("Gamepads are not available: {}", error);
+                None
+            }
+        };
+        Gamepads { gilrs }
+    }
+
+    pub fn direction(&mut self) -> Option<Direction> {
+        // Get the direction held on the D-pad or left stick of any gamepad
+        let gilrs = self.gilrs.as_mut()?;
+        while gilrs.next_event().is_some() {}
+
+        for (_, gamepad) in gilrs.gamepads() {
+            let buttons = [
+                (Button::DPadUp, Direction::North),
+                (Button::DPadDown, Direction::South),
+                (Button::DPadRight, Direction::East),
+                (Button::DPadLeft, Direction::West)
+            ];
+            if let Some((_, direction)) = buttons.iter().find(|(button, _)| gamepad.is_pressed(*button)) {
+                return Some(*direction);
+            }
+            // Sticks point up with positive values
+            let (x, y) = (gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY));
+            if x.abs().max(y.abs()) >= STICK_DEADZONE {
+                return Some(match (x.abs() > y.abs(), x > 0.0, y > 0.0) {
+                    (true, true, _) => Direction::East,
+                    (true, false, _) => Direction::West,
+                    (false, _, true) => Direction::North,
+                    (false, _, false) => Direction::South
+                });
+            }
+        }
+        None
+    }
+}
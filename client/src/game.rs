@@ -1,11 +1,52 @@
 use std::collections::HashMap;
+use log::debug;
 
 use macroquad::prelude::*;
+use ::rand::{rngs::StdRng, Rng, SeedableRng};
+use crate::audio::Cue;
 use crate::snek::Snek;
-use shared::{Coord, Direction, SnekId, UpdateResult, MAX_PLAYERS};
+use crate::theme::Theme;
+use shared::{
+    Coord, Direction, GameMode, MatchSettings, PickupKind, SnekId, TeamId, UpdateResult,
+    MAX_PLAYERS, NO_TEAM, STARTING_LENGTH, TRON_STARTING_LENGTH
+};
 
+// Pickups spawn on a fixed tick schedule, from the seed shared by the server
+const FOOD_INTERVAL: u32 = 10;
+const MAX_FOOD: usize = 3;
+const POWER_UP_INTERVAL: u32 = 50;
+const MAX_POWER_UPS: usize = 2;
+const SPAWN_ATTEMPTS: usize = 20;
 
-const STARTING_LENGTH: i32 = 10;
+const SPEED_BOOST_TICKS: u32 = 30;
+const GHOST_TICKS: u32 = 15;
+
+// Space left around the board for the HUD
+const BOARD_MARGIN: i32 = 50;
+
+// Cells never get smaller than this, larger boards scroll instead. Zooming
+// in multiplies the size of the cells that fit the window
+const MIN_CELL_SIZE: i32 = 8;
+const MAX_ZOOM: f32 = 8.0;
+const ZOOM_STEP: f32 = 1.25;
+
+// How many ticks the local simulation may run ahead of the server
+const MAX_PREDICTED_TICKS: u32 = 10;
+
+pub struct Player {
+    pub name: String,
+    pub color: Color
+}
+
+// The simulation as of the last tick confirmed by the server
+#[derive(Clone)]
+struct Snapshot {
+    sneks: HashMap<SnekId, Snek>,
+    internal_grid: Vec<SnekId>,
+    pickups: Vec<(Coord, PickupKind)>,
+    rng: StdRng,
+    tick: u32
+}
 
 pub struct Game {
     pub screen_width: i32,
@@ -18,11 +59,42 @@ pub struct Game {
     grid_height: i32,
     grid_x: i32,
     grid_y: i32,
+    view: (i32, i32, i32, i32),
+    zoom: f32,
+    camera_focus: Coord,
     internal_grid: Vec<SnekId>,
     pub sneks: HashMap<SnekId, Snek>,
+    pub players: HashMap<SnekId, Player>,
+    pub pickups: Vec<(Coord, PickupKind)>,
     my_snek_id: SnekId,
 
-    started: bool
+    settings: MatchSettings,
+    rng: StdRng,
+    tick: u32,
+    remaining_time: Option<u16>,
+    // The last tick of a timed match, sent when the time runs out
+    final_tick: Option<u32>,
+    countdown: Option<u8>,
+    start_time: f64,
+    elapsed_time: f64,
+    ping: Option<f64>,
+    result: Option<String>,
+    started: bool,
+
+    // Where the sneks were before the last tick, to slide them from there
+    smooth: bool,
+    previous_cells: HashMap<SnekId, Vec<Coord>>,
+    last_step_time: f64,
+
+    // The board is predicted ahead of the server from the last confirmed
+    // tick, replaying the local inputs by the tick they were made on
+    confirmed: Option<Snapshot>,
+    last_confirmed_time: f64,
+    inputs: Vec<(u32, Direction)>,
+
+    // Sounds to play for what happened since the last frame
+    cues: Vec<Cue>,
+    theme: Theme
 }
 
 impl Game {
@@ -31,11 +103,34 @@ impl Game {
     {
         Game { 
             screen_width, screen_height, grid_size, grid_x_count, grid_y_count,
-            grid_width: grid_size * grid_x_count, grid_height: grid_size * grid_y_count, grid_x: 50, grid_y: 50,
+            grid_width: grid_size * grid_x_count, grid_height: grid_size * grid_y_count, grid_x: BOARD_MARGIN, grid_y: BOARD_MARGIN,
+            view: (BOARD_MARGIN, BOARD_MARGIN, grid_size * grid_x_count, grid_size * grid_y_count),
+            zoom: 1.0,
+            camera_focus: (grid_x_count / 2, grid_y_count / 2),
             internal_grid: vec![0; (grid_x_count * grid_y_count) as usize],
             sneks: HashMap::new(),
+            players: HashMap::new(),
+            pickups: Vec::new(),
             my_snek_id: 0,
-            started: false
+            settings: MatchSettings::default(),
+            rng: StdRng::seed_from_u64(0),
+            tick: 0,
+            remaining_time: None,
+            final_tick: None,
+            countdown: None,
+            start_time: 0.0,
+            elapsed_time: 0.0,
+            ping: None,
+            result: None,
+            started: false,
+            smooth: false,
+            previous_cells: HashMap::new(),
+            last_step_time: 0.0,
+            confirmed: None,
+            last_confirmed_time: 0.0,
+            inputs: Vec::new(),
+            cues: Vec::new(),
+            theme: Theme::classic()
         }
     }
 
@@ -49,23 +144,300 @@ impl Game {
         self.my_snek_id = id;
     }
 
+    pub fn add_player(&mut self, id: SnekId, name: String, color: Color) {
+        // Remember the name and color a player picked
+        self.players.insert(id, Player { name, color });
+    }
+
+    pub fn get_player_name(&self, id: SnekId) -> String {
+        // Get the name of a player, falling back to their snek id
+        match self.players.get(&id) {
+            Some(player) if !player.name.is_empty() => player.name.clone(),
+            _ => format!("Snek {}", id)
+        }
+    }
+
+    pub fn set_smooth(&mut self, smooth: bool) {
+        // Draw the sneks sliding between cells instead of jumping
+        self.smooth = smooth;
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        // Set the colors the game is drawn with
+        self.theme = theme;
+    }
+
+    pub fn get_theme(&self) -> &Theme {
+        // Get the colors the game is drawn with
+        &self.theme
+    }
+
     pub fn has_started(&self) -> bool {
         // Check if started
         self.started
     }
 
-    pub fn start_game(&mut self) {
-        // Start the game
+    pub fn start_game(&mut self, settings: MatchSettings) {
+        // Start the game, every client seeds its pickups the same way
+        self.settings = settings;
+        self.rng = StdRng::seed_from_u64(settings.seed);
+
+        // Resize the board to the one picked by the server
+        self.grid_x_count = settings.board_width as i32;
+        self.grid_y_count = settings.board_height as i32;
+        self.layout(self.screen_width, self.screen_height);
+        self.internal_grid = vec![0; (self.grid_x_count * self.grid_y_count) as usize];
+
+        // Lay the sneks out again now that the game mode is known
+        let ids = self.get_all_snek_ids();
+        self.sneks.clear();
+        for id in ids {
+            self.spawn_snek(id).unwrap();
+        }
+        self.start_time = get_time();
         self.started = true;
+        self.confirmed = Some(self.snapshot());
+        self.inputs.clear();
     }
 
     pub fn end_game(&mut self) {
-        // End the game
+        // End the game, showing the board as the server last confirmed it
+        self.restore_confirmed();
         self.started = false;
     }
 
+    pub fn get_confirmed_tick(&self) -> u32 {
+        // Get the last tick confirmed by the server
+        self.confirmed.as_ref().map_or(0, |snapshot| snapshot.tick)
+    }
+
+    pub fn confirm_tick(&mut self, moves: &[(SnekId, Direction)]) {
+        // Roll back to the last confirmed tick and play the one the server
+        // sent, then predict ahead again from there
+        let predicted_tick = self.tick;
+        let last_step_time = self.last_step_time;
+        self.restore_confirmed();
+        for &(id, direction) in moves {
+            self.move_snek(id, direction);
+        }
+        self.step(false);
+        self.confirmed = Some(self.snapshot());
+        self.last_confirmed_time = get_time();
+
+        // Inputs before the confirmed tick are dropped, except the latest one
+        // which the server may not have received yet
+        let confirmed_tick = self.tick;
+        if let Some(latest) = self.inputs.iter().rposition(|(tick, _)| *tick <= confirmed_tick) {
+            self.inputs.drain(..latest);
+        }
+
+        self.predict();
+        // The latest turn always shows, even when the replay stopped short of it
+        if let Some(&(_, direction)) = self.inputs.last() {
+            self.move_snek(self.my_snek_id, direction);
+        }
+        // Keep sliding smoothly when the replay ends on the same tick
+        if self.tick == predicted_tick {
+            self.last_step_time = last_step_time;
+        }
+    }
+
+    fn predict(&mut self) {
+        // Run ahead of the server by about the round trip time, which is when
+        // the server will get the inputs made now. Waiting longer than a tick
+        // for the next confirmation means packets are late, so the lead stops
+        // growing there instead of guessing the other sneks further ahead
+        if self.confirmed.is_none() || self.get_confirmed_tick() == 0 || self.is_time_up() {
+            return;
+        }
+        let tick_seconds = self.settings.tick_millis as f64 / 1000.0;
+        let lead = (get_time() - self.last_confirmed_time).min(tick_seconds) + self.ping.unwrap_or(0.0);
+        let target = self.get_confirmed_tick() + ((lead / tick_seconds) as u32).min(MAX_PREDICTED_TICKS);
+        while self.tick < target {
+            let tick = self.tick + 1;
+            if let Some(&(_, direction)) = self.inputs.iter().rev().find(|(input_tick, _)| *input_tick <= tick) {
+                self.move_snek(self.my_snek_id, direction);
+            }
+            self.step(true);
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        // Copy the simulation state
+        Snapshot {
+            sneks: self.sneks.clone(),
+            internal_grid: self.internal_grid.clone(),
+            pickups: self.pickups.clone(),
+            rng: self.rng.clone(),
+            tick: self.tick
+        }
+    }
+
+    fn restore_confirmed(&mut self) {
+        // Throw away the predicted ticks. Which direction was last sent to the
+        // server is not part of the simulation, so it is kept
+        let snapshot = match self.confirmed.clone() {
+            Some(snapshot) => snapshot,
+            None => return
+        };
+        let sent_direction = self.sneks.get(&self.my_snek_id).map(|snek| snek.previous_direction);
+        self.sneks = snapshot.sneks;
+        self.internal_grid = snapshot.internal_grid;
+        self.pickups = snapshot.pickups;
+        self.rng = snapshot.rng;
+        self.tick = snapshot.tick;
+        if let (Some(direction), Some(snek)) = (sent_direction, self.sneks.get_mut(&self.my_snek_id)) {
+            snek.previous_direction = direction;
+        }
+    }
+
+    pub fn set_result(&mut self, result: String) {
+        // Remember the result the server sent at the end of the game
+        self.result = Some(result);
+    }
+
+    pub fn get_result(&self) -> Option<String> {
+        // Get the result of the game once it has ended
+        self.result.clone()
+    }
+
+    pub fn get_settings(&self) -> &MatchSettings {
+        // Get the settings of the match
+        &self.settings
+    }
+
+    pub fn get_board_rect(&self) -> (f32, f32, f32, f32) {
+        // Get the position and size of the visible part of the board on screen
+        let (x, y, width, height) = self.view;
+        (x as f32, y as f32, width as f32, height as f32)
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        // Check if only part of the board fits in the window
+        self.view.2 < self.grid_width || self.view.3 < self.grid_height
+    }
+
+    pub fn get_visible_cells(&self) -> (f32, f32, f32, f32) {
+        // Get the part of the board in view, in cells
+        let size = self.grid_size as f32;
+        let (x, y, width, height) = self.view;
+        ((x - self.grid_x) as f32 / size, (y - self.grid_y) as f32 / size, width as f32 / size, height as f32 / size)
+    }
+
+    pub fn get_tick(&self) -> u32 {
+        // Get the number of ticks simulated so far
+        self.tick
+    }
+
+    pub fn get_elapsed_time(&self) -> f64 {
+        // Get the seconds played since the start of the match
+        self.elapsed_time
+    }
+
+    pub fn set_ping(&mut self, ping: f64) {
+        // Set the latest round trip time to the server, in seconds
+        self.ping = Some(ping);
+    }
+
+    pub fn get_ping(&self) -> Option<f64> {
+        // Get the latest round trip time to the server
+        self.ping
+    }
+
+    pub fn get_remaining_time(&self) -> Option<u16> {
+        // Get the time left in a timed match
+        self.remaining_time
+    }
+
+    pub fn set_countdown(&mut self, seconds: u8) {
+        // Set the seconds left before the first tick, the clock starts at 0
+        if seconds == 0 {
+            self.countdown = None;
+            self.start_time = get_time();
+            self.cues.push(Cue::Go);
+        } else {
+            self.countdown = Some(seconds);
+            self.cues.push(Cue::Countdown);
+        }
+    }
+
+    pub fn push_cue(&mut self, cue: Cue) {
+        // Queue a sound to play
+        self.cues.push(cue);
+    }
+
+    pub fn take_cues(&mut self) -> Vec<Cue> {
+        // Get the sounds queued since the last frame
+        std::mem::take(&mut self.cues)
+    }
+
+    pub fn get_countdown(&self) -> Option<u8> {
+        // Get the seconds left before the first tick
+        self.countdown
+    }
+
+    pub fn set_remaining_time(&mut self, seconds: u16) {
+        // Set the time left in a timed match. Once it runs out the standings
+        // come from the confirmed board
+        self.remaining_time = Some(seconds);
+        if seconds == 0 {
+            self.restore_confirmed();
+        }
+    }
+
+    pub fn is_time_up(&self) -> bool {
+        // Check if the clock of a timed match has run out
+        self.remaining_time == Some(0)
+    }
+
+    pub fn set_final_tick(&mut self, tick: u32) {
+        // Remember the last tick of a timed match, played before the standings are final
+        self.final_tick = Some(tick);
+    }
+
+    pub fn has_final_standings(&self) -> bool {
+        // Check if the last tick of a timed match has been confirmed
+        self.final_tick.is_some_and(|tick| self.get_confirmed_tick() >= tick)
+    }
+
+    pub fn get_scoreboard(&self) -> Vec<SnekId> {
+        // Get the living sneks by rank, followed by the dead players by id
+        let mut ids = self.get_ranking();
+        let mut dead: Vec<SnekId> = self.players.keys().cloned().filter(|id| !self.is_alive(*id)).collect();
+        dead.sort_unstable();
+        ids.extend(dead);
+        ids
+    }
+
+    pub fn get_ranking(&self) -> Vec<SnekId> {
+        // Rank the living sneks by length, then by who reached their length first
+        let mut ids = self.get_all_snek_ids();
+        ids.sort_by(|a, b| {
+            let (snek_a, snek_b) = (&self.sneks[a], &self.sneks[b]);
+            snek_b.length().cmp(&snek_a.length())
+                .then(snek_a.grown_tick.cmp(&snek_b.grown_tick))
+                .then(a.cmp(b))
+        });
+        ids
+    }
+
+    pub fn get_scores(&self) -> Vec<u8> {
+        // Serialize the standings for the ScoreEvent
+        let mut payload = Vec::new();
+        for id in self.get_ranking() {
+            let snek = &self.sneks[&id];
+            payload.push(id);
+            payload.extend((snek.length() as u16).to_be_bytes().iter());
+            payload.extend(snek.grown_tick.to_be_bytes().iter());
+        }
+        payload
+    }
+
     fn get_spawn(&self, id: SnekId) -> Result<(Coord, Vec<Coord>, Direction), &str> {
         // Get the spawn locations based on # of players
+        if self.settings.mode == GameMode::Tron {
+            return self.get_tron_spawn(id);
+        }
         match id {
             1 => Ok((
                 (STARTING_LENGTH - 1, 0), 
@@ -91,6 +463,35 @@ impl Game {
         }
     }
 
+    fn get_tron_spawn(&self, id: SnekId) -> Result<(Coord, Vec<Coord>, Direction), &str> {
+        // Tron sneks start short in the middle of each side, facing inward
+        let (mid_x, mid_y) = (self.grid_x_count / 2, self.grid_y_count / 2);
+        let (far_x, far_y) = (self.grid_x_count - 1, self.grid_y_count - 1);
+        match id {
+            1 => Ok((
+                (TRON_STARTING_LENGTH, mid_y),
+                (1..TRON_STARTING_LENGTH).rev().map(|x| (x, mid_y)).collect(),
+                Direction::East
+            )),
+            2 => Ok((
+                (far_x - TRON_STARTING_LENGTH, mid_y),
+                (far_x - TRON_STARTING_LENGTH + 1..far_x).map(|x| (x, mid_y)).collect(),
+                Direction::West
+            )),
+            3 => Ok((
+                (mid_x, TRON_STARTING_LENGTH),
+                (1..TRON_STARTING_LENGTH).rev().map(|y| (mid_x, y)).collect(),
+                Direction::South
+            )),
+            4 => Ok((
+                (mid_x, far_y - TRON_STARTING_LENGTH),
+                (far_y - TRON_STARTING_LENGTH + 1..far_y).map(|y| (mid_x, y)).collect(),
+                Direction::North
+            )),
+            _ => Err("Exceeded player count!")
+        }
+    }
+
     pub fn spawn_snek(&mut self, id: SnekId) -> Result<(), &str> {
         // Spawn the snek at specified location
         if self.sneks.len() >= MAX_PLAYERS {
@@ -102,54 +503,382 @@ impl Game {
         for coord in &body {
             Game::set_snek_at(coord.0, coord.1, id, self.grid_x_count, &mut self.internal_grid);
         }
-        self.sneks.insert(id, Snek { id, head, body, previous_direction: Direction::Invalid, direction });
+        self.sneks.insert(id, Snek::new(id, head, body, direction));
         Ok(())
     }
 
-    pub fn update(&mut self, should_update: bool) {
-        // Update sneks
-        if self.started && should_update {
-            let mut dead: Vec<SnekId> = Vec::new();
-            // Check for collisions
-            for (id, snek) in self.sneks.iter_mut() {
-                // println!("Snek of {} is going {}", id, snek.direction as u8);
-                let result = Game::update_snek(snek, &mut self.internal_grid, self.grid_x_count, self.grid_y_count);
-                match result {
-                    UpdateResult::WallCollision => {
-                        dead.push(*id);
-                    },
-                    UpdateResult::PlayerCollision(_) => {
-                        dead.push(*id);
-                    },
-                    _ => {}
-                }
-            }
-            // Remove the dead sneks
-            for id in dead {
-                // println!("snek {} died!", id);
-                Game::remove_snek(id, &mut self.sneks, &mut self.internal_grid, self.grid_x_count);
-            }
+    pub fn layout(&mut self, screen_width: i32, screen_height: i32) {
+        // Fit the board in the middle of the window, with cells as large as
+        // the window and zoom allow. A board larger than the window scrolls
+        // to keep the local snek in the middle
+        self.screen_width = screen_width;
+        self.screen_height = screen_height;
+        let view_width = (screen_width - 2 * BOARD_MARGIN).max(1);
+        let view_height = (screen_height - 2 * BOARD_MARGIN).max(1);
+        let fit = (view_width / self.grid_x_count).min(view_height / self.grid_y_count);
+        self.grid_size = ((fit as f32 * self.zoom) as i32).max(MIN_CELL_SIZE);
+        self.grid_width = self.grid_size * self.grid_x_count;
+        self.grid_height = self.grid_size * self.grid_y_count;
+
+        if let Some(snek) = self.sneks.get(&self.my_snek_id) {
+            self.camera_focus = snek.head;
+        }
+        self.grid_x = Game::place_axis(screen_width, view_width, self.grid_width,
+            self.camera_focus.0 * self.grid_size + self.grid_size / 2);
+        self.grid_y = Game::place_axis(screen_height, view_height, self.grid_height,
+            self.camera_focus.1 * self.grid_size + self.grid_size / 2);
+        self.view = (
+            self.grid_x.max(BOARD_MARGIN),
+            self.grid_y.max(BOARD_MARGIN),
+            self.grid_width.min(view_width),
+            self.grid_height.min(view_height)
+        );
+    }
+
+    fn place_axis(screen: i32, view: i32, board: i32, focus: i32) -> i32 {
+        // Get where the board starts along one axis. It is centered when it
+        // fits, otherwise the focus is centered without scrolling past the edges
+        if board <= view {
+            (screen - board) / 2
+        } else {
+            (BOARD_MARGIN + view / 2 - focus).clamp(BOARD_MARGIN + view - board, BOARD_MARGIN)
         }
-        
-        // Draw the sneks
+    }
+
+    fn handle_zoom(&mut self) {
+        // Zoom in and out with the mouse wheel or the + and - keys
+        let (_, wheel) = mouse_wheel();
+        if wheel > 0.0 || is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            self.zoom = (self.zoom * ZOOM_STEP).min(MAX_ZOOM);
+        } else if wheel < 0.0 || is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            self.zoom = (self.zoom / ZOOM_STEP).max(1.0);
+        }
+    }
+
+    pub fn update(&mut self) {
+        // Follow the size of the window
+        self.layout(screen_width() as i32, screen_height() as i32);
+
+        // Predict the sneks up to now
+        if self.started && self.countdown.is_none() {
+            self.elapsed_time = get_time() - self.start_time;
+            self.predict();
+        }
+
+        // Draw the pickups
+        for ((x, y), kind) in &self.pickups {
+            draw_circle((self.grid_x + x * self.grid_size) as f32 + self.grid_size as f32 / 2.0,
+                (self.grid_y + y * self.grid_size) as f32 + self.grid_size as f32 / 2.0,
+                self.grid_size as f32 / 3.0, self.pickup_color(*kind));
+        }
+
+        // Draw the sneks, ghosts are see-through
         for (_, snek) in self.sneks.iter() {
-            draw_rectangle((self.grid_x + snek.head.0 * self.grid_size) as f32, 
-                (self.grid_y + snek.head.1 * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, YELLOW);
-            draw_rectangle_lines((self.grid_x + snek.head.0 * self.grid_size) as f32, 
-            (self.grid_y + snek.head.1 * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, 2.0, BLACK);
+            let alpha = if snek.is_ghost() { 0.4 } else { 1.0 };
+            let (head_color, body_color) = self.get_snek_colors(snek.id);
+            // Powered up sneks get their head outlined in the color of the power-up
+            let (outline, thickness) = if !snek.is_powered_up() {
+                (self.theme.background, 2.0)
+            } else if snek.shielded {
+                (self.pickup_color(PickupKind::Shield), 4.0)
+            } else if snek.speed_ticks > 0 {
+                (self.pickup_color(PickupKind::SpeedBoost), 4.0)
+            } else {
+                (self.pickup_color(PickupKind::Ghost), 4.0)
+            };
+            if self.smooth {
+                self.draw_smooth_snek(snek, Color { a: alpha, ..head_color }, Color { a: alpha, ..body_color }, outline, thickness);
+                if self.theme.markers {
+                    self.draw_smooth_markers(snek);
+                }
+                if self.countdown.is_some() {
+                    self.draw_heading(snek, head_color);
+                }
+                continue;
+            }
 
             for (x, y) in &snek.body {
                 draw_rectangle((self.grid_x + x * self.grid_size) as f32, 
-                    (self.grid_y + y * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, RED);
+                    (self.grid_y + y * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, Color { a: alpha, ..body_color });
                 draw_rectangle_lines((self.grid_x + x * self.grid_size) as f32, 
-                    (self.grid_y + y * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, 2.0, BLACK);
+                    (self.grid_y + y * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, 2.0, self.theme.background);
+                if self.theme.markers {
+                    let center = self.cell_center((*x, *y));
+                    self.draw_marker(snek.id, center.x, center.y, self.grid_size as f32);
+                }
+            }
+
+            draw_rectangle((self.grid_x + snek.head.0 * self.grid_size) as f32, 
+                (self.grid_y + snek.head.1 * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, Color { a: alpha, ..head_color });
+            draw_rectangle_lines((self.grid_x + snek.head.0 * self.grid_size) as f32, 
+            (self.grid_y + snek.head.1 * self.grid_size) as f32, self.grid_size as f32, self.grid_size as f32, thickness, outline);
+
+            // Point out where each snek is heading until the countdown ends
+            if self.countdown.is_some() {
+                self.draw_heading(snek, head_color);
             }
         }
 
+        // Hide whatever was drawn outside of the view when scrolling
+        let (view_x, view_y, view_width, view_height) = self.get_board_rect();
+        let (screen_width, screen_height) = (self.screen_width as f32, self.screen_height as f32);
+        let background = self.theme.background;
+        draw_rectangle(0.0, 0.0, screen_width, view_y, background);
+        draw_rectangle(0.0, view_y + view_height, screen_width, screen_height - view_y - view_height, background);
+        draw_rectangle(0.0, view_y, view_x, view_height, background);
+        draw_rectangle(view_x + view_width, view_y, screen_width - view_x - view_width, view_height, background);
+
         // Draw the grid
         const THICKNESS: f32 = 10.0;
-        draw_rectangle_lines(self.offset_x(0) as f32 - THICKNESS / 2.0, self.offset_y(0) as f32 - THICKNESS / 2.0,
-            self.grid_width as f32 + THICKNESS, self.grid_height as f32 + THICKNESS, THICKNESS, GREEN);
+        draw_rectangle_lines(view_x - THICKNESS / 2.0, view_y - THICKNESS / 2.0,
+            view_width + THICKNESS, view_height + THICKNESS, THICKNESS, self.theme.border);
+    }
+
+    fn draw_smooth_markers(&self, snek: &Snek) {
+        // Mark the body where the cells are, markers do not slide
+        for cell in &snek.body {
+            let center = self.cell_center(*cell);
+            self.draw_marker(snek.id, center.x, center.y, self.grid_size as f32);
+        }
+    }
+
+    pub fn draw_marker(&self, id: SnekId, x: f32, y: f32, size: f32) {
+        // Draw the shape of a snek in the middle of a cell, each snek has its
+        // own so they can be told apart without color
+        let color = self.theme.background;
+        let radius = size * 0.2;
+        match (id as usize + 3) % 4 {
+            0 => draw_circle(x, y, radius, color),
+            1 => draw_circle_lines(x, y, radius, 2.0, color),
+            2 => {
+                draw_line(x - radius, y - radius, x + radius, y + radius, 2.0, color);
+                draw_line(x - radius, y + radius, x + radius, y - radius, 2.0, color);
+            },
+            _ => draw_triangle(vec2(x, y - radius), vec2(x - radius, y + radius), vec2(x + radius, y + radius), color)
+        }
+    }
+
+    fn draw_smooth_snek(&self, snek: &Snek, head_color: Color, body_color: Color, outline: Color, thickness: f32) {
+        // Draw the snek as a rounded tube, each cell sliding from where it was
+        // on the previous tick toward where it is now
+        let size = self.grid_size as f32;
+        let tick_seconds = self.settings.tick_millis as f64 / 1000.0;
+        let progress = ((get_time() - self.last_step_time) / tick_seconds).clamp(0.0, 1.0) as f32;
+
+        let cells = snek.cells();
+        let previous = self.previous_cells.get(&snek.id).unwrap_or(&cells);
+        let centers: Vec<Vec2> = cells.iter().enumerate().map(|(i, cell)| {
+            // Cells grown this tick come out of the old tail
+            let from = previous.get(i).or(previous.last()).unwrap_or(cell);
+            self.cell_center(*from).lerp(self.cell_center(*cell), progress)
+        }).collect();
+
+        for pair in centers.windows(2).rev() {
+            draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, size * 0.8, body_color);
+            draw_circle(pair[1].x, pair[1].y, size * 0.4, body_color);
+        }
+        let head = centers[0];
+        draw_circle(head.x, head.y, size / 2.0, head_color);
+        draw_circle_lines(head.x, head.y, size / 2.0, thickness, outline);
+    }
+
+    fn cell_center(&self, (x, y): Coord) -> Vec2 {
+        // Get the position of the middle of a cell on screen
+        vec2(self.offset_x(x * self.grid_size) as f32 + self.grid_size as f32 / 2.0,
+            self.offset_y(y * self.grid_size) as f32 + self.grid_size as f32 / 2.0)
+    }
+
+    fn draw_heading(&self, snek: &Snek, color: Color) {
+        // Draw an arrow in the cell in front of the snek's head
+        let size = self.grid_size as f32;
+        let (dx, dy) = match snek.direction {
+            Direction::North => (0.0, -1.0),
+            Direction::South => (0.0, 1.0),
+            Direction::East => (1.0, 0.0),
+            Direction::West => (-1.0, 0.0),
+            Direction::Invalid => return
+        };
+        let center = vec2((self.grid_x + snek.head.0 * self.grid_size) as f32 + size / 2.0 + dx * size,
+            (self.grid_y + snek.head.1 * self.grid_size) as f32 + size / 2.0 + dy * size);
+        let forward = vec2(dx, dy) * size * 0.4;
+        let side = vec2(-dy, dx) * size * 0.3;
+        draw_triangle(center + forward, center - forward + side, center - forward - side, color);
+    }
+
+    fn step(&mut self, predicted: bool) {
+        // Advance the simulation by one tick. Sneks are handled in id order so
+        // every client ends up with the same board. Only the server decides
+        // deaths, a predicted death holds the snek still instead
+        self.tick += 1;
+        self.previous_cells = self.sneks.iter().map(|(id, snek)| (*id, snek.cells())).collect();
+        self.last_step_time = get_time();
+        let mut dead: Vec<SnekId> = Vec::new();
+        let mut ids = self.get_all_snek_ids();
+        ids.sort_unstable();
+        for id in ids {
+            let moves = if self.sneks[&id].speed_ticks > 0 { 2 } else { 1 };
+            for _ in 0..moves {
+                // Check for collisions
+                let snek = self.sneks.get_mut(&id).unwrap();
+                let result = Game::update_snek(snek, &mut self.internal_grid, self.grid_x_count, self.grid_y_count, &self.settings);
+                match result {
+                    UpdateResult::Nothing => {},
+                    _ => {
+                        // A shield absorbs the collision and the snek holds still
+                        if snek.shielded {
+                            snek.shielded = false;
+                        } else {
+                            dead.push(id);
+                        }
+                        break;
+                    }
+                }
+                let head = snek.head;
+                if let Some(kind) = self.take_pickup(head) {
+                    self.apply_pickup(id, kind);
+                    if !predicted && id == self.my_snek_id {
+                        self.cues.push(Cue::Eat);
+                    }
+                }
+            }
+        }
+        // Remove the dead sneks
+        for id in dead {
+            if predicted {
+                continue;
+            }
+            debug!(snek = id; "Snek died");
+            self.cues.push(if id == self.my_snek_id { Cue::OwnDeath } else { Cue::Death });
+            Game::remove_snek(id, &mut self.sneks, &mut self.internal_grid, self.grid_x_count);
+        }
+        for snek in self.sneks.values_mut() {
+            snek.tick_effects();
+        }
+        self.rebuild_grid();
+        self.spawn_pickups();
+    }
+
+    fn take_pickup(&mut self, coord: Coord) -> Option<PickupKind> {
+        // Remove the pickup at the specified location
+        let index = self.pickups.iter().position(|(pickup_coord, _)| *pickup_coord == coord)?;
+        Some(self.pickups.remove(index).1)
+    }
+
+    fn apply_pickup(&mut self, id: SnekId, kind: PickupKind) {
+        // Give the snek the effect of the pickup it ran over
+        let snek = self.sneks.get_mut(&id).unwrap();
+        match kind {
+            PickupKind::Food => {
+                snek.growth += 1;
+                snek.grown_tick = self.tick;
+            },
+            PickupKind::SpeedBoost => snek.speed_ticks = SPEED_BOOST_TICKS,
+            PickupKind::Ghost => snek.ghost_ticks = GHOST_TICKS,
+            PickupKind::Shield => snek.shielded = true,
+            PickupKind::Shrink => self.shrink_longest_opponent(id)
+        }
+    }
+
+    fn shrink_longest_opponent(&mut self, id: SnekId) {
+        // Cut the body of the longest opponent in half, ties go to the lowest id.
+        // Teammates are not opponents
+        let mut ids = self.get_all_snek_ids();
+        ids.sort_unstable();
+        let mut longest: Option<SnekId> = None;
+        for other in ids {
+            if other == id || self.settings.are_teammates(id, other) {
+                continue;
+            }
+            match longest {
+                Some(current) if self.sneks[&current].length() >= self.sneks[&other].length() => {},
+                _ => longest = Some(other)
+            }
+        }
+        if let Some(target) = longest {
+            let snek = self.sneks.get_mut(&target).unwrap();
+            let length = (snek.body.len() / 2).max(1);
+            snek.body.truncate(length);
+            // Sneks moving later in this tick must not hit the cut off cells
+            self.rebuild_grid();
+        }
+    }
+
+    fn spawn_pickups(&mut self) {
+        // Spawn food and power-ups on their schedules, Tron has no pickups
+        if self.settings.mode == GameMode::Tron {
+            return;
+        }
+        let food_count = self.pickups.iter().filter(|(_, kind)| *kind == PickupKind::Food).count();
+        if self.tick.is_multiple_of(FOOD_INTERVAL) && food_count < MAX_FOOD {
+            self.spawn_pickup(PickupKind::Food);
+        }
+        let power_up_count = self.pickups.len() - food_count;
+        if self.settings.power_ups && self.tick.is_multiple_of(POWER_UP_INTERVAL) && power_up_count < MAX_POWER_UPS {
+            let kind = match self.rng.gen_range(0..4) {
+                0 => PickupKind::SpeedBoost,
+                1 => PickupKind::Ghost,
+                2 => PickupKind::Shield,
+                _ => PickupKind::Shrink
+            };
+            self.spawn_pickup(kind);
+        }
+    }
+
+    fn spawn_pickup(&mut self, kind: PickupKind) {
+        // Place a pickup on a random free cell, giving up if the board is crowded
+        for _ in 0..SPAWN_ATTEMPTS {
+            let coord = (self.rng.gen_range(0..self.grid_x_count), self.rng.gen_range(0..self.grid_y_count));
+            let taken = Game::get_snek_at(coord.0, coord.1, self.grid_x_count, &self.internal_grid) != 0
+                || self.pickups.iter().any(|(pickup_coord, _)| *pickup_coord == coord);
+            if !taken {
+                self.pickups.push((coord, kind));
+                return;
+            }
+        }
+    }
+
+    pub fn pickup_color(&self, kind: PickupKind) -> Color {
+        // Get the color a pickup is drawn with
+        match kind {
+            PickupKind::Food => self.theme.food,
+            PickupKind::SpeedBoost => self.theme.speed_boost,
+            PickupKind::Ghost => self.theme.ghost,
+            PickupKind::Shield => self.theme.shield,
+            PickupKind::Shrink => self.theme.shrink
+        }
+    }
+
+    pub fn get_snek_colors(&self, id: SnekId) -> (Color, Color) {
+        // Get the head and body colors of a snek. Players pick their own color
+        // in free-for-all unless the theme picks for them, teammates share a
+        // color family and get darker the later they joined
+        let team = self.settings.team_of(id);
+        if team == NO_TEAM {
+            let body = match self.theme.players {
+                Some(colors) => colors[(id as usize + 3) % colors.len()],
+                None => self.players.get(&id).map(|player| player.color).unwrap_or(RED)
+            };
+            let head = Color::new((body.r + 1.0) / 2.0, (body.g + 1.0) / 2.0, (body.b + 1.0) / 2.0, 1.0);
+            return (head, body);
+        }
+        let family = self.get_team_color(team);
+        let shade = 1.0 - 0.3 * ((id - 1) / self.settings.team_count) as f32;
+        let body = Color::new(family.r * shade, family.g * shade, family.b * shade, 1.0);
+        let head = Color::new((body.r + 1.0) / 2.0, (body.g + 1.0) / 2.0, (body.b + 1.0) / 2.0, 1.0);
+        (head, body)
+    }
+
+    pub fn get_team_color(&self, team: TeamId) -> Color {
+        // Get the color family of a team
+        self.theme.teams[(team as usize - 1) % self.theme.teams.len()]
+    }
+
+    pub fn get_team_score(&self, team: TeamId) -> usize {
+        // Get the combined length of the team's living sneks
+        self.sneks.values()
+            .filter(|snek| self.settings.team_of(snek.id) == team)
+            .map(|snek| snek.length())
+            .sum()
     }
 
     pub fn get_all_snek_ids(&self) -> Vec<SnekId> {
@@ -162,6 +891,12 @@ impl Game {
         self.sneks.contains_key(&snek_id)
     }
     
+    pub fn is_blocked(&self, (x, y): Coord) -> bool {
+        // Check if moving into a cell crashes, off the board or into a snek
+        x < 0 || x >= self.grid_x_count || y < 0 || y >= self.grid_y_count
+            || Game::get_snek_at(x, y, self.grid_x_count, &self.internal_grid) != 0
+    }
+
     pub fn get_previous_snek_direction(&self, snek_id: SnekId) -> Direction {
         // Get the snek's previous direction
         self.sneks[&snek_id].previous_direction
@@ -184,23 +919,33 @@ impl Game {
         }
     }
 
-    pub fn handle_events(&mut self) {
-        // Get arrow key input
+    pub fn handle_events(&mut self, direction: Option<Direction>) {
+        // Turn the local snek in the direction held on the keyboard or gamepad
+        self.handle_zoom();
         if !self.has_started() {
             return;
         }
-        if is_key_down(KeyCode::Up) {
-            self.move_snek(self.my_snek_id, Direction::North);
-        } else if is_key_down(KeyCode::Down) {
-            self.move_snek(self.my_snek_id, Direction::South);
-        } else if is_key_down(KeyCode::Right) {
-            self.move_snek(self.my_snek_id, Direction::East);
-        } else if is_key_down(KeyCode::Left) {
-            self.move_snek(self.my_snek_id, Direction::West);
+        if let Some(direction) = direction {
+            self.input(direction);
         }
     }
 
-    fn update_snek(snek: &mut Snek, grid: &mut Vec<SnekId>, width: i32, height: i32) -> UpdateResult {
+    fn input(&mut self, direction: Direction) {
+        // Turn the local snek right away, and remember on which tick so the
+        // turn can be replayed after a rollback
+        let snek = match self.sneks.get_mut(&self.my_snek_id) {
+            Some(snek) => snek,
+            None => return
+        };
+        let previous = snek.direction;
+        snek.set_direction(direction);
+        if snek.direction != previous {
+            self.inputs.push((self.tick + 1, snek.direction));
+            self.cues.push(Cue::Turn);
+        }
+    }
+
+    fn update_snek(snek: &mut Snek, grid: &mut Vec<SnekId>, width: i32, height: i32, settings: &MatchSettings) -> UpdateResult {
         // snek.has_changed_direction = false;
         // Check for collisions
         let new_head = snek.get_new_head_coord();
@@ -210,6 +955,8 @@ impl Game {
         let snek_id = snek.id;
         match Game::get_snek_at(new_head.0, new_head.1, width, grid) {
             0 => {},
+            _ if snek.is_ghost() => {},
+            id if settings.are_teammates(snek_id, id) && !settings.friendly_fire => {},
             id => {
                 if id == snek_id {
                     return UpdateResult::WallCollision;
@@ -218,18 +965,26 @@ impl Game {
                 }
             }
         }
-        // Update the internal grid
+        // Update the internal grid, ghosts are left out of it
         // Add head
-        let head_index = Game::get_1d_index(new_head.0, new_head.1, width);
-        grid[head_index] = snek_id;
+        if !snek.is_ghost() {
+            let head_index = Game::get_1d_index(new_head.0, new_head.1, width);
+            grid[head_index] = snek_id;
+        }
 
-        // Remove old tail
-        let tail = snek.body.last().unwrap();
-        let tail_index = Game::get_1d_index(tail.0, tail.1, width);
-        grid[tail_index] = 0;
+        // Remove old tail unless the snek is growing. In Tron the tail never
+        // moves, so the trail stays deadly
+        let should_grow = snek.growth > 0 || settings.mode == GameMode::Tron;
+        if snek.growth > 0 {
+            snek.growth -= 1;
+        } else if !should_grow && !snek.is_ghost() {
+            let tail = snek.body.last().unwrap();
+            let tail_index = Game::get_1d_index(tail.0, tail.1, width);
+            grid[tail_index] = 0;
+        }
 
         // Advance the snek itself
-        snek.advance(false);
+        snek.advance(should_grow);
 
         return UpdateResult::Nothing;
     }
@@ -245,6 +1000,27 @@ impl Game {
         }
     }
 
+    fn rebuild_grid(&mut self) {
+        // Rebuild the internal grid from the sneks, which keeps it right after
+        // ghosts overlap other sneks and after sneks are shrunk. Where sneks
+        // overlap the highest id owns the cell, on every client alike
+        for cell in self.internal_grid.iter_mut() {
+            *cell = 0;
+        }
+        let mut ids = self.get_all_snek_ids();
+        ids.sort_unstable();
+        for id in ids {
+            let snek = &self.sneks[&id];
+            if snek.is_ghost() {
+                continue;
+            }
+            Game::set_snek_at(snek.head.0, snek.head.1, snek.id, self.grid_x_count, &mut self.internal_grid);
+            for coord in &snek.body {
+                Game::set_snek_at(coord.0, coord.1, snek.id, self.grid_x_count, &mut self.internal_grid);
+            }
+        }
+    }
+
     fn offset_x(&self, x: i32) -> i32 {
         // Offset x coordinate
         self.grid_x + x
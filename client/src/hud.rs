@@ -0,0 +1,110 @@
+use macroquad::prelude::*;
+use crate::game::Game;
+
+const HUD_FONT_SIZE: f32 = 24.0;
+const SPACING: f32 = 24.0;
+const MINIMAP_SIZE: f32 = 160.0;
+
+pub fn draw_hud(game: &Game) {
+    // Draw the match info above the board and the scoreboard below it
+    let (x, y, width, height) = game.get_board_rect();
+    let theme = game.get_theme();
+    let top_y = y - 18.0;
+
+    // Current tick and the time, which counts down in timed matches
+    let tick = format!("Tick {}", game.get_tick());
+    draw_text(&tick, x, top_y, HUD_FONT_SIZE, theme.dim_text);
+    let mut info_x = x + text_width(&tick) + SPACING;
+
+    let (seconds, label, color) = match game.get_remaining_time() {
+        Some(seconds) => (seconds as u64, "left", if seconds <= 10 { theme.warning } else { theme.text }),
+        None => (game.get_elapsed_time() as u64, "played", theme.text)
+    };
+    let time = format!("{}:{:02} {}", seconds / 60, seconds % 60, label);
+    draw_text(&time, info_x, top_y, HUD_FONT_SIZE, color);
+    info_x += text_width(&time) + SPACING;
+
+    // Team scores next to the time in team mode
+    let settings = game.get_settings();
+    if settings.is_team_mode() {
+        for team in 1..=settings.team_count {
+            let score = format!("Team {}: {}", team, game.get_team_score(team));
+            draw_text(&score, info_x, top_y, HUD_FONT_SIZE, game.get_team_color(team));
+            info_x += text_width(&score) + SPACING;
+        }
+    }
+
+    // Ping of the local player on the right
+    let ping = match game.get_ping() {
+        Some(ping) => format!("Ping {} ms", (ping * 1000.0).round()),
+        None => "Ping -".to_owned()
+    };
+    draw_text(&ping, x + width - text_width(&ping), top_y, HUD_FONT_SIZE, theme.dim_text);
+
+    // The countdown before the first tick, in the middle of the board
+    if let Some(seconds) = game.get_countdown() {
+        let text = seconds.to_string();
+        let size = measure_text(&text, None, 120, 1.0);
+        draw_text(&text, x + (width - size.width) / 2.0, y + (height + size.height) / 2.0, 120.0, theme.text);
+    }
+
+    // Every player with their color, length and whether they are still alive
+    let bottom_y = y + height + 36.0;
+    let mut entry_x = x;
+    for id in game.get_scoreboard() {
+        let alive = game.is_alive(id);
+        let (_, color) = game.get_snek_colors(id);
+        let mut name = game.get_player_name(id);
+        if id == game.get_my_snek_id() {
+            name.push_str(" (you)");
+        }
+        let text = if alive {
+            format!("{} {}", name, game.sneks[&id].length())
+        } else {
+            format!("{} dead", name)
+        };
+        draw_rectangle(entry_x, bottom_y - 14.0, 14.0, 14.0, color);
+        if theme.markers {
+            game.draw_marker(id, entry_x + 7.0, bottom_y - 7.0, 14.0);
+        }
+        draw_text(&text, entry_x + 20.0, bottom_y, HUD_FONT_SIZE, if alive { theme.text } else { theme.dim_text });
+        entry_x += 20.0 + text_width(&text) + SPACING;
+    }
+}
+
+pub fn draw_minimap(game: &Game) {
+    // Draw the whole board in the corner of the view when it does not fit
+    if !game.is_scrolling() {
+        return;
+    }
+    let (view_x, view_y, view_width, view_height) = game.get_board_rect();
+    let cell = MINIMAP_SIZE / game.grid_x_count.max(game.grid_y_count) as f32;
+    let (width, height) = (cell * game.grid_x_count as f32, cell * game.grid_y_count as f32);
+    let x = view_x + view_width - width - 10.0;
+    let y = view_y + view_height - height - 10.0;
+    let theme = game.get_theme();
+    draw_rectangle(x, y, width, height, Color { a: 0.75, ..theme.background });
+
+    for ((cell_x, cell_y), kind) in &game.pickups {
+        draw_rectangle(x + *cell_x as f32 * cell, y + *cell_y as f32 * cell, cell.max(2.0), cell.max(2.0), game.pickup_color(*kind));
+    }
+    for (id, snek) in game.sneks.iter() {
+        let (head_color, body_color) = game.get_snek_colors(*id);
+        for (cell_x, cell_y) in &snek.body {
+            draw_rectangle(x + *cell_x as f32 * cell, y + *cell_y as f32 * cell, cell.max(1.0), cell.max(1.0), body_color);
+        }
+        // Heads are drawn larger so every snek can be found at a glance
+        draw_rectangle(x + snek.head.0 as f32 * cell - 1.0, y + snek.head.1 as f32 * cell - 1.0,
+            cell + 2.0, cell + 2.0, head_color);
+    }
+
+    // Outline the part of the board in view
+    let (visible_x, visible_y, visible_width, visible_height) = game.get_visible_cells();
+    draw_rectangle_lines(x + visible_x * cell, y + visible_y * cell, visible_width * cell, visible_height * cell, 1.0, theme.text);
+    draw_rectangle_lines(x, y, width, height, 2.0, theme.border);
+}
+
+fn text_width(text: &str) -> f32 {
+    // Get the width of HUD text
+    measure_text(text, None, HUD_FONT_SIZE as u16, 1.0).width
+}
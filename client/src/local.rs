@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use macroquad::prelude::get_time;
+use shared::{Direction, MatchSettings, SnekId, TeamId, COUNTDOWN_SECONDS, INVALID_ID, NO_TEAM};
+use crate::ai::{self, Difficulty};
+use crate::audio::Cue;
+use crate::config::NAMED_COLORS;
+use crate::controls::KeyBindings;
+use crate::game::Game;
+
+// Colors of the local players, picked to be far apart
+const PLAYER_COLORS: [usize; 4] = [0, 4, 3, 2];
+
+// Who plays a local match. Players at the keyboard come first, then the bots
+#[derive(Copy, Clone)]
+pub struct LocalOptions {
+    pub humans: u8,
+    pub bots: u8,
+    pub difficulty: Difficulty
+}
+
+impl LocalOptions {
+    pub fn new() -> LocalOptions {
+        LocalOptions { humans: 2, bots: 0, difficulty: Difficulty::Medium }
+    }
+
+    pub fn is_solo(&self) -> bool {
+        // A single player uses their own controls and follows their snek
+        self.humans == 1
+    }
+}
+
+// Runs a match on one machine, doing what the server does for online play:
+// counting down, ticking the moves of every player and deciding the result
+pub struct LocalMatch {
+    pub options: LocalOptions,
+    settings: MatchSettings,
+    countdown_started: f64,
+    countdown: u8,
+    clock_started: f64,
+    remaining: u16,
+    last_tick: f64,
+    directions: HashMap<SnekId, Direction>,
+    finished: bool
+}
+
+impl LocalMatch {
+    pub fn start(options: LocalOptions, game: &mut Game) -> LocalMatch {
+        // Put every player and bot on the board and start the countdown
+        let player_count = options.humans + options.bots;
+        let settings = MatchSettings { seed: ::rand::random(), player_count, ..MatchSettings::default() };
+        for id in 1..=player_count {
+            let [r, g, b] = NAMED_COLORS[PLAYER_COLORS[id as usize - 1]].1;
+            let name = if id > options.humans {
+                format!("Bot {}", id - options.humans)
+            } else {
+                format!("Player {}", id)
+            };
+            game.add_player(id, name, macroquad::prelude::Color::from_rgba(r, g, b, 255));
+            game.spawn_snek(id).unwrap();
+        }
+        if options.is_solo() {
+            game.set_my_snek_id(1);
+        }
+        game.start_game(settings);
+        game.set_countdown(COUNTDOWN_SECONDS);
+
+        let mut local = LocalMatch {
+            options,
+            settings,
+            countdown_started: get_time(),
+            countdown: COUNTDOWN_SECONDS,
+            clock_started: 0.0,
+            remaining: settings.duration,
+            last_tick: 0.0,
+            directions: HashMap::new(),
+            finished: false
+        };
+        local.choose_bot_directions(game);
+        local
+    }
+
+    pub fn handle_events(&mut self, bindings: &[KeyBindings], solo_direction: Option<Direction>) {
+        // Remember the direction each player picked for the next tick. A single
+        // player turns with the controls used online instead
+        if self.options.is_solo() {
+            if let Some(direction) = solo_direction {
+                self.directions.insert(1, direction);
+            }
+            return;
+        }
+        for (i, keys) in bindings.iter().take(self.options.humans as usize).enumerate() {
+            if let Some(direction) = keys.direction() {
+                self.directions.insert(i as SnekId + 1, direction);
+            }
+        }
+    }
+
+    pub fn update(&mut self, game: &mut Game) {
+        // Advance the match on the same schedule the server uses
+        if self.finished {
+            return;
+        }
+        let now = get_time();
+        if self.countdown > 0 {
+            let countdown = COUNTDOWN_SECONDS.saturating_sub((now - self.countdown_started) as u8);
+            if countdown != self.countdown {
+                self.countdown = countdown;
+                game.set_countdown(countdown);
+                self.clock_started = now;
+                self.last_tick = now - self.tick_seconds();
+            }
+            if countdown > 0 {
+                return;
+            }
+        }
+
+        if self.settings.is_timed() {
+            let remaining = self.settings.duration.saturating_sub((now - self.clock_started) as u16);
+            if remaining != self.remaining {
+                self.remaining = remaining;
+                game.set_remaining_time(remaining);
+                if remaining == 0 {
+                    let (winner, winning_team) = self.settings.get_timed_winner(&game.get_scores());
+                    self.finish(game, winner, winning_team);
+                    return;
+                }
+            }
+        }
+
+        if now - self.last_tick < self.tick_seconds() {
+            return;
+        }
+        self.last_tick = now;
+        let moves: Vec<(SnekId, Direction)> = self.directions.iter().map(|(id, direction)| (*id, *direction)).collect();
+        game.confirm_tick(&moves);
+
+        if let Some((winner, winning_team)) = self.settings.get_winner(&game.get_all_snek_ids()) {
+            self.finish(game, winner, winning_team);
+            return;
+        }
+        // Nobody is left to watch the bots play it out, the longest one wins
+        let ranking = game.get_ranking();
+        if !ranking.iter().any(|&id| id <= self.options.humans) {
+            self.finish(game, ranking[0], NO_TEAM);
+            return;
+        }
+        self.choose_bot_directions(game);
+    }
+
+    fn choose_bot_directions(&mut self, game: &Game) {
+        // Let every bot still alive pick its move for the next tick
+        let first_bot = self.options.humans + 1;
+        for id in first_bot..first_bot + self.options.bots {
+            if let Some(direction) = ai::choose_direction(game, id, self.options.difficulty) {
+                self.directions.insert(id, direction);
+            }
+        }
+    }
+
+    fn tick_seconds(&self) -> f64 {
+        self.settings.tick_millis as f64 / 1000.0
+    }
+
+    fn finish(&mut self, game: &mut Game, winner: SnekId, winning_team: TeamId) {
+        // Show the result the server would have sent
+        self.finished = true;
+        let tie = winner == INVALID_ID && winning_team == NO_TEAM;
+        let result = if winning_team != NO_TEAM {
+            format!("Team {} won!", winning_team)
+        } else if self.options.is_solo() && winner == 1 {
+            "You won!".to_owned()
+        } else if winner != INVALID_ID {
+            format!("{} won!", game.get_player_name(winner))
+        } else {
+            "It's a tie!".to_owned()
+        };
+        // Alone against the bots, losing sounds like it does online
+        let lost = if winning_team != NO_TEAM { winning_team != self.settings.team_of(1) } else { winner != 1 };
+        let cue = if tie {
+            Cue::Tie
+        } else if self.options.is_solo() && lost {
+            Cue::Loss
+        } else {
+            Cue::Win
+        };
+        game.push_cue(cue);
+        game.set_result(result);
+        game.end_game();
+    }
+}
@@ -1,9 +1,18 @@
+mod ai;
+mod audio;
 mod client;
+mod config;
+mod controls;
 mod game;
+mod hud;
+mod local;
+mod menu;
+mod settings;
 mod snek;
+mod theme;
+mod ui;
 
 fn main() {
     // Start the client
-    println!("Starting client...");
     client::client();
 }
\ No newline at end of file
@@ -0,0 +1,254 @@
+use macroquad::prelude::*;
+use shared::{Direction, MAX_NAME_LENGTH, MAX_PLAYERS};
+use crate::ai::DIFFICULTIES;
+use crate::config::NAMED_COLORS;
+use crate::controls::{self, KeyBindings, PRESETS};
+use crate::local::LocalOptions;
+use crate::theme::Theme;
+use crate::ui;
+
+const FIELD_WIDTH: f32 = 400.0;
+const BUTTON_WIDTH: f32 = 190.0;
+const SMALL_BUTTON_WIDTH: f32 = 170.0;
+const BUTTON_HEIGHT: f32 = 50.0;
+const SWATCH_SIZE: f32 = 36.0;
+
+#[derive(PartialEq)]
+pub enum MenuAction {
+    Nothing,
+    Play,
+    Local,
+    Controls,
+    Rematch,
+    Leave,
+    Quit
+}
+
+pub struct MainMenu {
+    pub server_address: String,
+    pub name: String,
+    pub color: [u8; 3],
+    pub error: Option<String>,
+    colors: Vec<[u8; 3]>,
+    focused_field: usize
+}
+
+impl MainMenu {
+    pub fn new(server_address: String, name: String, color: [u8; 3]) -> MainMenu {
+        // A color given on the command line joins the named colors
+        let mut colors: Vec<[u8; 3]> = NAMED_COLORS.iter().map(|(_, color)| *color).collect();
+        if !colors.contains(&color) {
+            colors.push(color);
+        }
+        MainMenu { server_address, name, color, error: None, colors, focused_field: 0 }
+    }
+
+    pub fn draw(&mut self, theme: &Theme) -> MenuAction {
+        // Draw the main menu with the server address field, name field and
+        // color picker
+        let center_x = screen_width() / 2.0;
+        let left = center_x - FIELD_WIDTH / 2.0;
+        ui::draw_centered_text("Snek", center_x, 150.0, 100.0, theme.border);
+
+        // Tab moves between the two text fields
+        if is_key_pressed(KeyCode::Tab) {
+            self.focused_field = (self.focused_field + 1) % 2;
+        }
+        if ui::text_field(left, 260.0, FIELD_WIDTH, "Server address", &mut self.server_address, 64, self.focused_field == 0, theme) {
+            self.focused_field = 0;
+        }
+        if ui::text_field(left, 360.0, FIELD_WIDTH, "Name", &mut self.name, MAX_NAME_LENGTH, self.focused_field == 1, theme) {
+            self.focused_field = 1;
+        }
+
+        draw_text("Color", left, 452.0, ui::FONT_SIZE * 0.8, theme.dim_text);
+        for (i, &color) in self.colors.iter().enumerate() {
+            let x = left + i as f32 * (SWATCH_SIZE + 12.0);
+            let swatch_color = Color::from_rgba(color[0], color[1], color[2], 255);
+            if ui::color_swatch(x, 462.0, SWATCH_SIZE, swatch_color, color == self.color, theme) {
+                self.color = color;
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui::draw_centered_text(error, center_x, 545.0, 24.0, theme.warning);
+        }
+
+        let left = center_x - SMALL_BUTTON_WIDTH * 2.0 - 30.0;
+        let play = ui::button(left, 570.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, "Play", theme);
+        let local = ui::button(left + SMALL_BUTTON_WIDTH + 20.0, 570.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, "Local", theme);
+        let controls = ui::button(left + 2.0 * (SMALL_BUTTON_WIDTH + 20.0), 570.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, "Controls", theme);
+        let quit = ui::button(left + 3.0 * (SMALL_BUTTON_WIDTH + 20.0), 570.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, "Quit", theme);
+        if play || is_key_pressed(KeyCode::Enter) {
+            MenuAction::Play
+        } else if local {
+            MenuAction::Local
+        } else if controls {
+            MenuAction::Controls
+        } else if quit {
+            MenuAction::Quit
+        } else {
+            MenuAction::Nothing
+        }
+    }
+}
+
+pub struct ControlsMenu {
+    waiting_for: Option<Direction>
+}
+
+impl ControlsMenu {
+    pub fn new() -> ControlsMenu {
+        ControlsMenu { waiting_for: None }
+    }
+
+    pub fn draw(&mut self, bindings: &mut KeyBindings, theme: &Theme) -> MenuAction {
+        // Draw the key of every direction. Clicking one waits for the next key
+        // pressed, Escape keeps the old key
+        let center_x = screen_width() / 2.0;
+        ui::draw_centered_text("Controls", center_x, 150.0, 60.0, theme.border);
+
+        if let Some(direction) = self.waiting_for {
+            if let Some(key) = get_last_key_pressed() {
+                if let (Some(name), false) = (controls::key_name(key), key == KeyCode::Escape) {
+                    for (bound, binding) in bindings.keys_mut() {
+                        if bound == direction {
+                            *binding = name.to_owned();
+                        }
+                    }
+                }
+                self.waiting_for = None;
+            }
+        }
+
+        let labels = ["Up", "Down", "Left", "Right"];
+        for (i, (direction, binding)) in bindings.keys_mut().into_iter().enumerate() {
+            let y = 210.0 + i as f32 * 65.0;
+            draw_text(labels[i], center_x - BUTTON_WIDTH - 10.0, y + BUTTON_HEIGHT / 2.0 + ui::FONT_SIZE / 4.0, ui::FONT_SIZE, theme.text);
+            let label = if self.waiting_for == Some(direction) { "Press a key" } else { binding.as_str() };
+            if ui::button(center_x + 10.0, y, BUTTON_WIDTH, BUTTON_HEIGHT, label, theme) {
+                self.waiting_for = Some(direction);
+            }
+        }
+
+        // Presets replace every key at once
+        for (i, (name, keys)) in PRESETS.iter().enumerate() {
+            let x = center_x - BUTTON_WIDTH * 1.5 - 20.0 + i as f32 * (BUTTON_WIDTH + 20.0);
+            if ui::button(x, 490.0, BUTTON_WIDTH, BUTTON_HEIGHT, name, theme) {
+                *bindings = KeyBindings::preset(*keys);
+                self.waiting_for = None;
+            }
+        }
+        ui::draw_centered_text("Gamepads turn with the D-pad or the left stick", center_x, 580.0, 24.0, theme.dim_text);
+
+        if ui::button(center_x - BUTTON_WIDTH / 2.0, 620.0, BUTTON_WIDTH, BUTTON_HEIGHT, "Back", theme) {
+            self.waiting_for = None;
+            MenuAction::Leave
+        } else {
+            MenuAction::Nothing
+        }
+    }
+}
+
+pub fn draw_local_setup(options: &mut LocalOptions, controls: &KeyBindings, bindings: &[KeyBindings], theme: &Theme) -> MenuAction {
+    // Draw the setup of a local match, with the number of players sharing the
+    // keyboard, the bots playing against them and the keys of each player
+    let center_x = screen_width() / 2.0;
+    ui::draw_centered_text("Local game", center_x, 130.0, 60.0, theme.border);
+
+    // Between two and four sneks play, so picking one count may change the other
+    let left = center_x - SMALL_BUTTON_WIDTH * 2.0 - 30.0;
+    for humans in 1..=MAX_PLAYERS as u8 {
+        let x = left + (humans - 1) as f32 * (SMALL_BUTTON_WIDTH + 20.0);
+        let label = if humans == 1 { "1 player".to_owned() } else { format!("{} players", humans) };
+        if ui::button(x, 170.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, &label, theme) {
+            options.humans = humans;
+            options.bots = options.bots.clamp(2u8.saturating_sub(humans), MAX_PLAYERS as u8 - humans);
+        }
+        if humans == options.humans {
+            draw_rectangle_lines(x - 4.0, 166.0, SMALL_BUTTON_WIDTH + 8.0, BUTTON_HEIGHT + 8.0, 3.0, theme.text);
+        }
+    }
+    for bots in 0..MAX_PLAYERS as u8 {
+        let x = left + bots as f32 * (SMALL_BUTTON_WIDTH + 20.0);
+        let label = match bots {
+            0 => "No bots".to_owned(),
+            1 => "1 bot".to_owned(),
+            _ => format!("{} bots", bots)
+        };
+        if ui::button(x, 240.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, &label, theme) {
+            options.bots = bots;
+            options.humans = options.humans.clamp(2u8.saturating_sub(bots).max(1), MAX_PLAYERS as u8 - bots);
+        }
+        if bots == options.bots {
+            draw_rectangle_lines(x - 4.0, 236.0, SMALL_BUTTON_WIDTH + 8.0, BUTTON_HEIGHT + 8.0, 3.0, theme.text);
+        }
+    }
+    if options.bots > 0 {
+        for (i, (name, difficulty)) in DIFFICULTIES.iter().enumerate() {
+            let x = center_x - SMALL_BUTTON_WIDTH * 1.5 - 20.0 + i as f32 * (SMALL_BUTTON_WIDTH + 20.0);
+            if ui::button(x, 310.0, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT, name, theme) {
+                options.difficulty = *difficulty;
+            }
+            if *difficulty == options.difficulty {
+                draw_rectangle_lines(x - 4.0, 306.0, SMALL_BUTTON_WIDTH + 8.0, BUTTON_HEIGHT + 8.0, 3.0, theme.text);
+            }
+        }
+    }
+
+    for i in 0..options.humans as usize {
+        let keys = if options.is_solo() {
+            format!("{} or a gamepad", controls.describe())
+        } else {
+            bindings.get(i).map(|keys| keys.describe()).unwrap_or("no keys".to_owned())
+        };
+        ui::draw_centered_text(&format!("Player {}: {}", i + 1, keys), center_x, 410.0 + i as f32 * 36.0, ui::FONT_SIZE, theme.text);
+    }
+    ui::draw_centered_text("Keys are set under local_controls in snek-client.toml", center_x, 570.0, 24.0, theme.dim_text);
+
+    let start = ui::button(center_x - BUTTON_WIDTH - 10.0, 620.0, BUTTON_WIDTH, BUTTON_HEIGHT, "Start", theme);
+    let back = ui::button(center_x + 10.0, 620.0, BUTTON_WIDTH, BUTTON_HEIGHT, "Back", theme);
+    if start || is_key_pressed(KeyCode::Enter) {
+        MenuAction::Play
+    } else if back {
+        MenuAction::Leave
+    } else {
+        MenuAction::Nothing
+    }
+}
+
+pub fn draw_lobby(server_address: &str, players: &[String], theme: &Theme) -> MenuAction {
+    // Draw the waiting screen with every player waiting for a match
+    let center_x = screen_width() / 2.0;
+    ui::draw_centered_text("Waiting for players...", center_x, 150.0, 50.0, theme.border);
+    ui::draw_centered_text(server_address, center_x, 200.0, 24.0, theme.dim_text);
+
+    for (i, name) in players.iter().enumerate() {
+        ui::draw_centered_text(name, center_x, 280.0 + i as f32 * 40.0, ui::FONT_SIZE, theme.text);
+    }
+
+    if ui::button(center_x - BUTTON_WIDTH / 2.0, screen_height() - 150.0, BUTTON_WIDTH, BUTTON_HEIGHT, "Leave", theme) {
+        MenuAction::Leave
+    } else {
+        MenuAction::Nothing
+    }
+}
+
+pub fn draw_end_screen(result: &str, theme: &Theme) -> MenuAction {
+    // Draw the result of the match over the board, with rematch and quit buttons
+    let center_x = screen_width() / 2.0;
+    let center_y = screen_height() / 2.0;
+    draw_rectangle(center_x - 250.0, center_y - 120.0, 500.0, 240.0, Color { a: 0.85, ..theme.background });
+    draw_rectangle_lines(center_x - 250.0, center_y - 120.0, 500.0, 240.0, 3.0, theme.border);
+    ui::draw_centered_text(result, center_x, center_y - 30.0, 50.0, theme.text);
+
+    let rematch = ui::button(center_x - BUTTON_WIDTH - 10.0, center_y + 30.0, BUTTON_WIDTH, BUTTON_HEIGHT, "Rematch", theme);
+    let quit = ui::button(center_x + 10.0, center_y + 30.0, BUTTON_WIDTH, BUTTON_HEIGHT, "Quit", theme);
+    if rematch {
+        MenuAction::Rematch
+    } else if quit {
+        MenuAction::Quit
+    } else {
+        MenuAction::Nothing
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use crate::controls::KeyBindings;
+use crate::theme::ThemeColors;
+
+const SETTINGS_FILE: &str = "snek-client.toml";
+
+// Preferences kept between runs of the client
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientSettings {
+    pub volume: f32,
+    pub muted: bool,
+    pub theme: String,
+    pub colors: ThemeColors,
+    pub controls: KeyBindings,
+    // One set of keys per player in a local match
+    pub local_controls: Vec<KeyBindings>,
+    // Set when the file could not be parsed, so saving does not replace it
+    #[serde(skip)]
+    unreadable: bool
+}
+
+impl Default for ClientSettings {
+    fn default() -> ClientSettings {
+        ClientSettings {
+            volume: 0.8,
+            muted: false,
+            theme: "classic".to_owned(),
+            colors: ThemeColors::default(),
+            controls: KeyBindings::default(),
+            local_controls: KeyBindings::local_defaults(),
+            unreadable: false
+        }
+    }
+}
+
+impl ClientSettings {
+    pub fn load() -> ClientSettings {
+        // Read the settings file, a missing or broken file gives the defaults
+        let contents = match fs::read_to_string(SETTINGS_FILE) {
+            Ok(contents) => contents,
+            Err(_) => return ClientSettings::default()
+        };
+        toml::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring invalid {}: {}", SETTINGS_FILE, error);
+            ClientSettings { unreadable: true, ..ClientSettings::default() }
+        })
+    }
+
+    pub fn save(&self) {
+        // Write the settings file, failing only costs the changes
+        if self.unreadable {
+            warn!("Not saving over {}, fix or remove it to keep changes", SETTINGS_FILE);
+            return;
+        }
+        let result = toml::to_string(self)
+            .map_err(|error| error.to_string())
+            .and_then(|contents| fs::write(SETTINGS_FILE, contents).map_err(|error| error.to_string()));
+        if let Err(error) = result {
+            error!("Could not save {}: {}", SETTINGS_FILE, error);
+        }
+    }
+}
@@ -1,14 +1,26 @@
 use shared::{Coord, Direction, SnekId};
 
+#[derive(Clone)]
 pub struct Snek {
     pub id: SnekId,
     pub head: Coord,
     pub body: Vec<Coord>,
     pub previous_direction: Direction,
-    pub direction: Direction
+    pub direction: Direction,
+    pub growth: u32,
+    pub grown_tick: u32,
+    pub speed_ticks: u32,
+    pub ghost_ticks: u32,
+    pub shielded: bool
 }
 
 impl Snek {
+    pub fn new(id: SnekId, head: Coord, body: Vec<Coord>, direction: Direction) -> Snek {
+        Snek {
+            id, head, body, previous_direction: Direction::Invalid, direction,
+            growth: 0, grown_tick: 0, speed_ticks: 0, ghost_ticks: 0, shielded: false
+        }
+    }
 
     pub fn get_new_head_coord(&self) -> Coord {
         // Get the new head coord based on direction inputted
@@ -32,7 +44,7 @@ impl Snek {
         };
         self.direction = direction;
     }
-    
+
     pub fn advance(&mut self, should_grow: bool) {
         // Move the snek to new position and grow if necessary
         if !should_grow {
@@ -41,4 +53,32 @@ impl Snek {
         self.body.insert(0, self.head);
         self.head = self.get_new_head_coord();
     }
-}
\ No newline at end of file
+
+    pub fn cells(&self) -> Vec<Coord> {
+        // Get every cell of the snek, from the head to the tail
+        let mut cells = vec![self.head];
+        cells.extend(self.body.iter());
+        cells
+    }
+
+    pub fn length(&self) -> usize {
+        // Get the length of the snek including its head
+        self.body.len() + 1
+    }
+
+    pub fn is_ghost(&self) -> bool {
+        // Check if the snek currently passes through bodies
+        self.ghost_ticks > 0
+    }
+
+    pub fn is_powered_up(&self) -> bool {
+        // Check if any power-up is currently active
+        self.speed_ticks > 0 || self.ghost_ticks > 0 || self.shielded
+    }
+
+    pub fn tick_effects(&mut self) {
+        // Count down the timed power-ups by one tick
+        self.speed_ticks = self.speed_ticks.saturating_sub(1);
+        self.ghost_ticks = self.ghost_ticks.saturating_sub(1);
+    }
+}
@@ -0,0 +1,163 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::config::parse_color;
+
+pub const THEME_NAMES: [&str; 3] = ["classic", "high-contrast", "colorblind"];
+
+// Colors picked in the settings file on top of the chosen theme, by name or #rrggbb
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeColors {
+    pub background: Option<String>,
+    pub border: Option<String>,
+    pub text: Option<String>,
+    pub food: Option<String>,
+    pub speed_boost: Option<String>,
+    pub ghost: Option<String>,
+    pub shield: Option<String>,
+    pub shrink: Option<String>,
+    pub players: Option<Vec<String>>,
+    pub teams: Option<Vec<String>>,
+    pub markers: Option<bool>
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub background: Color,
+    pub border: Color,
+    pub text: Color,
+    pub dim_text: Color,
+    pub warning: Color,
+    pub food: Color,
+    pub speed_boost: Color,
+    pub ghost: Color,
+    pub shield: Color,
+    pub shrink: Color,
+    pub teams: [Color; 4],
+    // Replaces the colors players picked, by snek id
+    pub players: Option<[Color; 4]>,
+    // Draws a different shape on each snek so they differ by more than hue
+    pub markers: bool
+}
+
+impl Theme {
+    pub fn classic() -> Theme {
+        // The original colors
+        Theme {
+            background: BLACK,
+            border: GREEN,
+            text: WHITE,
+            dim_text: LIGHTGRAY,
+            warning: RED,
+            food: PINK,
+            speed_boost: ORANGE,
+            ghost: LIGHTGRAY,
+            shield: SKYBLUE,
+            shrink: PURPLE,
+            teams: [RED, BLUE, GREEN, ORANGE],
+            players: None,
+            markers: false
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        // Saturated colors on black, with markers
+        Theme {
+            background: BLACK,
+            border: WHITE,
+            text: WHITE,
+            dim_text: WHITE,
+            warning: YELLOW,
+            food: WHITE,
+            speed_boost: YELLOW,
+            ghost: GRAY,
+            shield: SKYBLUE,
+            shrink: MAGENTA,
+            teams: [YELLOW, SKYBLUE, MAGENTA, WHITE],
+            players: Some([YELLOW, SKYBLUE, MAGENTA, WHITE]),
+            markers: true
+        }
+    }
+
+    pub fn colorblind() -> Theme {
+        // The Okabe-Ito palette, which stays distinct with every common form
+        // of color blindness
+        let orange = hex(0xe69f00);
+        let sky_blue = hex(0x56b4e9);
+        let green = hex(0x009e73);
+        let yellow = hex(0xf0e442);
+        let blue = hex(0x0072b2);
+        let vermillion = hex(0xd55e00);
+        let pink = hex(0xcc79a7);
+        Theme {
+            background: BLACK,
+            border: sky_blue,
+            text: WHITE,
+            dim_text: LIGHTGRAY,
+            warning: orange,
+            food: yellow,
+            speed_boost: orange,
+            ghost: LIGHTGRAY,
+            shield: sky_blue,
+            shrink: pink,
+            teams: [orange, blue, green, pink],
+            players: Some([orange, sky_blue, vermillion, blue]),
+            markers: true
+        }
+    }
+
+    pub fn load(name: &str, colors: &ThemeColors) -> Result<Theme, String> {
+        // Start from a built-in theme and apply the colors from the settings file
+        let mut theme = match name.trim().to_lowercase().as_str() {
+            "classic" => Theme::classic(),
+            "high-contrast" => Theme::high_contrast(),
+            "colorblind" => Theme::colorblind(),
+            _ => return Err(format!("Unknown theme {}, pick one of {}", name, THEME_NAMES.join(", ")))
+        };
+        let fields = [
+            (&colors.background, &mut theme.background),
+            (&colors.border, &mut theme.border),
+            (&colors.text, &mut theme.text),
+            (&colors.food, &mut theme.food),
+            (&colors.speed_boost, &mut theme.speed_boost),
+            (&colors.ghost, &mut theme.ghost),
+            (&colors.shield, &mut theme.shield),
+            (&colors.shrink, &mut theme.shrink)
+        ];
+        for (text, color) in fields {
+            if let Some(text) = text {
+                *color = to_color(parse_color(text)?);
+            }
+        }
+        if let Some(players) = &colors.players {
+            theme.players = Some(parse_palette(players)?);
+        }
+        if let Some(teams) = &colors.teams {
+            theme.teams = parse_palette(teams)?;
+        }
+        if let Some(markers) = colors.markers {
+            theme.markers = markers;
+        }
+        Ok(theme)
+    }
+}
+
+fn parse_palette(texts: &[String]) -> Result<[Color; 4], String> {
+    // Parse one color for each of the four players or teams
+    if texts.len() != 4 {
+        return Err("Player and team colors need exactly 4 colors".to_owned());
+    }
+    let mut palette = [BLACK; 4];
+    for (color, text) in palette.iter_mut().zip(texts) {
+        *color = to_color(parse_color(text)?);
+    }
+    Ok(palette)
+}
+
+fn to_color(rgb: [u8; 3]) -> Color {
+    Color::from_rgba(rgb[0], rgb[1], rgb[2], 255)
+}
+
+fn hex(value: u32) -> Color {
+    to_color([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
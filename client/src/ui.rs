@@ -0,0 +1,61 @@
+use macroquad::prelude::*;
+use crate::theme::Theme;
+
+pub const FONT_SIZE: f32 = 30.0;
+
+pub fn draw_centered_text(text: &str, center_x: f32, y: f32, font_size: f32, color: Color) {
+    // Draw text centered horizontally on a point
+    let width = measure_text(text, None, font_size as u16, 1.0).width;
+    draw_text(text, center_x - width / 2.0, y, font_size, color);
+}
+
+pub fn is_hovered(x: f32, y: f32, width: f32, height: f32) -> bool {
+    // Check if the mouse is over a rectangle
+    let (mouse_x, mouse_y) = mouse_position();
+    mouse_x >= x && mouse_x <= x + width && mouse_y >= y && mouse_y <= y + height
+}
+
+pub fn button(x: f32, y: f32, width: f32, height: f32, label: &str, theme: &Theme) -> bool {
+    // Draw a button and check if it was clicked this frame
+    let hovered = is_hovered(x, y, width, height);
+    // The fill is a tint of the theme so buttons stand out on any background
+    draw_rectangle(x, y, width, height, if hovered { Color { a: 0.35, ..theme.border } } else { Color { a: 0.2, ..theme.dim_text } });
+    draw_rectangle_lines(x, y, width, height, 2.0, theme.border);
+    draw_centered_text(label, x + width / 2.0, y + height / 2.0 + FONT_SIZE / 4.0, FONT_SIZE, theme.text);
+    hovered && is_mouse_button_pressed(MouseButton::Left)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn text_field(x: f32, y: f32, width: f32, label: &str, text: &mut String, max_length: usize, focused: bool, theme: &Theme) -> bool {
+    // Draw a labelled text box, typing goes into it while it has focus.
+    // Returns true when the box was clicked
+    let height = FONT_SIZE + 14.0;
+    draw_text(label, x, y - 8.0, FONT_SIZE * 0.8, theme.dim_text);
+    draw_rectangle(x, y, width, height, Color { a: 0.1, ..theme.text });
+    draw_rectangle_lines(x, y, width, height, 2.0, if focused { theme.border } else { theme.dim_text });
+
+    if focused {
+        while let Some(character) = get_char_pressed() {
+            if !character.is_control() && text.chars().count() < max_length {
+                text.push(character);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            text.pop();
+        }
+    }
+    // Blink a cursor after the text while focused
+    let cursor = if focused && (get_time() * 2.0) as i64 % 2 == 0 { "_" } else { "" };
+    draw_text(&format!("{}{}", text, cursor), x + 8.0, y + height / 2.0 + FONT_SIZE / 4.0, FONT_SIZE, theme.text);
+
+    is_hovered(x, y, width, height) && is_mouse_button_pressed(MouseButton::Left)
+}
+
+pub fn color_swatch(x: f32, y: f32, size: f32, color: Color, selected: bool, theme: &Theme) -> bool {
+    // Draw a color to pick from, outlined when selected. Returns true when clicked
+    draw_rectangle(x, y, size, size, color);
+    if selected {
+        draw_rectangle_lines(x - 4.0, y - 4.0, size + 8.0, size + 8.0, 3.0, theme.text);
+    }
+    is_hovered(x, y, size, size) && is_mouse_button_pressed(MouseButton::Left)
+}
@@ -0,0 +1,224 @@
+use std::env;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{ArgAction, Parser, ValueEnum};
+use serde::Deserialize;
+use shared::{GameMode, MatchSettings, MAX_PLAYERS};
+use shared::logging::LOG_ENV;
+
+#[derive(Copy, Clone, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ModeName {
+    Classic,
+    Tron
+}
+
+// Every value can come from the command line or the config file,
+// the command line wins when both are given
+#[derive(Parser)]
+#[command(name = "snek-server", about = "Hosts multiplayer Snek matches")]
+struct Args {
+    /// TOML file to read the settings from
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Address to listen on, repeat to listen on several. IPv6 addresses may
+    /// be written in brackets, and "any" listens on every IPv4 and IPv6 interface
+    #[arg(short, long)]
+    address: Vec<String>,
+    /// Port to listen on
+    #[arg(short, long)]
+    port: Option<u16>,
+    /// Number of players in a match
+    #[arg(long)]
+    players: Option<u8>,
+    /// Number of teams, 0 for free-for-all
+    #[arg(long)]
+    teams: Option<u8>,
+    /// Let teammates kill each other
+    #[arg(long, action = ArgAction::SetTrue, overrides_with = "no_friendly_fire")]
+    friendly_fire: bool,
+    /// Keep teammates from killing each other
+    #[arg(long, action = ArgAction::SetTrue, overrides_with = "friendly_fire")]
+    no_friendly_fire: bool,
+    /// Simulation ticks per second, defaults to the game mode's speed
+    #[arg(long)]
+    tick_rate: Option<u16>,
+    /// Width of the board in cells
+    #[arg(long)]
+    board_width: Option<u8>,
+    /// Height of the board in cells
+    #[arg(long)]
+    board_height: Option<u8>,
+    /// Game mode to play
+    #[arg(short, long, value_enum)]
+    mode: Option<ModeName>,
+    /// Match length in seconds, 0 for no time limit
+    #[arg(long)]
+    duration: Option<u16>,
+    /// Spawn power-ups next to the food
+    #[arg(long, action = ArgAction::SetTrue, overrides_with = "no_power_ups")]
+    power_ups: bool,
+    /// Spawn only food
+    #[arg(long, action = ArgAction::SetTrue, overrides_with = "power_ups")]
+    no_power_ups: bool,
+    /// Seconds without a packet before a client times out
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Seconds between heartbeats sent to idle clients
+    #[arg(long)]
+    heartbeat: Option<u64>,
+    /// Port on this machine to serve the status report on, over plain HTTP
+    #[arg(long)]
+    status_port: Option<u16>,
+    /// Levels to log at, such as "debug" or "info,snek_server::server=trace".
+    /// Overrides the SNEK_LOG environment variable
+    #[arg(long)]
+    log: Option<String>,
+    /// Log JSON lines instead of text
+    #[arg(long)]
+    log_json: bool
+}
+
+// The config file takes either one address or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FileAddresses {
+    One(String),
+    Many(Vec<String>)
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    address: Option<FileAddresses>,
+    port: Option<u16>,
+    players: Option<u8>,
+    teams: Option<u8>,
+    friendly_fire: Option<bool>,
+    tick_rate: Option<u16>,
+    board_width: Option<u8>,
+    board_height: Option<u8>,
+    mode: Option<ModeName>,
+    duration: Option<u16>,
+    power_ups: Option<bool>,
+    timeout: Option<u64>,
+    heartbeat: Option<u64>,
+    status_port: Option<u16>,
+    log: Option<String>,
+    log_json: Option<bool>
+}
+
+pub struct ServerConfig {
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub settings: MatchSettings,
+    pub timeout: Duration,
+    pub heartbeat: Duration,
+    pub status_port: Option<u16>,
+    pub log: Option<String>,
+    pub log_json: bool
+}
+
+pub fn load() -> Result<ServerConfig, String> {
+    // Read the command line, then fill in the gaps from the config file
+    let args = Args::parse();
+    let file = match &args.config {
+        Some(path) => {
+            let contents = fs::read_to_string(path)
+                .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+            toml::from_str(&contents)
+                .map_err(|error| format!("Invalid config file {}: {}", path.display(), error))?
+        },
+        None => FileConfig::default()
+    };
+
+    let defaults = MatchSettings::default();
+    let mode = match args.mode.or(file.mode).unwrap_or(ModeName::Classic) {
+        ModeName::Classic => GameMode::Classic,
+        ModeName::Tron => GameMode::Tron
+    };
+    let tick_millis = match args.tick_rate.or(file.tick_rate) {
+        Some(0) => return Err("The tick rate must be above 0".to_owned()),
+        Some(rate) => (1000 / rate).max(1),
+        None => mode.default_tick_millis()
+    };
+    let settings = MatchSettings {
+        mode,
+        power_ups: switch(args.power_ups, args.no_power_ups).or(file.power_ups).unwrap_or(defaults.power_ups),
+        player_count: args.players.or(file.players).unwrap_or(defaults.player_count),
+        team_count: args.teams.or(file.teams).unwrap_or(defaults.team_count),
+        friendly_fire: switch(args.friendly_fire, args.no_friendly_fire).or(file.friendly_fire).unwrap_or(defaults.friendly_fire),
+        duration: args.duration.or(file.duration).unwrap_or(defaults.duration),
+        board_width: args.board_width.or(file.board_width).unwrap_or(defaults.board_width),
+        board_height: args.board_height.or(file.board_height).unwrap_or(defaults.board_height),
+        tick_millis,
+        ..defaults
+    };
+
+    // Make sure the settings describe a playable match
+    if settings.player_count < 2 || settings.player_count as usize > MAX_PLAYERS {
+        return Err(format!("The number of players must be between 2 and {}", MAX_PLAYERS));
+    }
+    if settings.team_count == 1 || settings.team_count > settings.player_count {
+        return Err("The number of teams must be 0, or between 2 and the number of players".to_owned());
+    }
+    let (min_width, min_height) = settings.min_board_size();
+    if settings.board_width < min_width || settings.board_height < min_height {
+        return Err(format!("This mode and number of players need a board of at least {} by {} cells", min_width, min_height));
+    }
+
+    let address_names = if !args.address.is_empty() {
+        args.address
+    } else {
+        match file.address {
+            Some(FileAddresses::One(address)) => vec![address],
+            Some(FileAddresses::Many(addresses)) => addresses,
+            None => vec![Ipv4Addr::LOCALHOST.to_string()]
+        }
+    };
+    let mut addresses = Vec::new();
+    for name in &address_names {
+        for address in parse_address(name)? {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+    if addresses.is_empty() {
+        return Err("At least one address to listen on is needed".to_owned());
+    }
+
+    Ok(ServerConfig {
+        addresses,
+        port: args.port.or(file.port).unwrap_or(8080),
+        settings,
+        timeout: Duration::from_secs(args.timeout.or(file.timeout).unwrap_or(5)),
+        heartbeat: Duration::from_secs(args.heartbeat.or(file.heartbeat).unwrap_or(1)),
+        status_port: args.status_port.or(file.status_port),
+        // SNEK_LOG wins over the file, the flag wins over both
+        log: args.log.or(env::var(LOG_ENV).ok()).or(file.log),
+        log_json: args.log_json || file.log_json.unwrap_or(false)
+    })
+}
+
+fn parse_address(name: &str) -> Result<Vec<IpAddr>, String> {
+    // Parse an address to listen on, "any" means both IPv4 and IPv6
+    match name.trim() {
+        "any" => Ok(vec![IpAddr::V6(Ipv6Addr::UNSPECIFIED), IpAddr::V4(Ipv4Addr::UNSPECIFIED)]),
+        name => name.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()
+            .map(|address| vec![address])
+            .map_err(|_| format!("Invalid address to listen on: {}", name))
+    }
+}
+
+fn switch(on: bool, off: bool) -> Option<bool> {
+    // Read a pair of flags such as --power-ups and --no-power-ups, giving
+    // neither leaves the setting to the config file
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None
+    }
+}
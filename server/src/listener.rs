@@ -0,0 +1,130 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::thread;
+use std::time::Duration;
+use crossbeam_channel::{Receiver, Select, SendError, Sender};
+use laminar::{Config, ErrorKind, Packet, Socket, SocketEvent};
+use log::info;
+
+// Packets and bytes that went through the sockets since the server started
+#[derive(Copy, Clone, Default)]
+pub struct Traffic {
+    pub packets_in: u64,
+    pub bytes_in: u64,
+    pub packets_out: u64,
+    pub bytes_out: u64
+}
+
+// One laminar socket per listen address, so the server can serve IPv4 and
+// IPv6 clients at the same time. Every client is answered through the socket
+// its packets arrived on, which is the address it sent them to
+pub struct Listener {
+    sockets: Vec<(SocketAddr, Sender<Packet>, Receiver<SocketEvent>)>,
+    routes: RefCell<HashMap<SocketAddr, usize>>,
+    next_socket: Cell<usize>,
+    traffic: Cell<Traffic>
+}
+
+impl Listener {
+    pub fn bind(addresses: &[IpAddr], port: u16, config: Config) -> Result<Listener, ErrorKind> {
+        // Bind every address, IPv6 first. On most systems an IPv6 socket on
+        // [::] also accepts IPv4, which makes binding 0.0.0.0 afterwards fail.
+        // Laminar binds the socket itself, so IPV6_V6ONLY can't be set
+        // beforehand, and the failed bind is what tells the two cases apart
+        let mut addresses = addresses.to_vec();
+        addresses.sort_by_key(|address| address.is_ipv4());
+
+        let mut sockets: Vec<(SocketAddr, Sender<Packet>, Receiver<SocketEvent>)> = Vec::new();
+        for address in addresses {
+            let address = SocketAddr::new(address, port);
+            let mut socket = match Socket::bind_with_config(address, config.clone()) {
+                Ok(socket) => socket,
+                Err(ErrorKind::IOError(error)) if error.kind() == io::ErrorKind::AddrInUse
+                    && address.ip().is_unspecified()
+                    && sockets.iter().any(|(local, _, _)| local.ip().is_unspecified()) => {
+                    info!(address:% = address; "Already covered by the dual-stack socket");
+                    continue;
+                },
+                Err(error) => return Err(error)
+            };
+            info!(address:% = address; "Listening");
+
+            let (sender, receiver) = (
+                socket.get_packet_sender(), socket.get_event_receiver());
+            let _thread = thread::spawn(move || socket.start_polling());
+            sockets.push((address, sender, receiver));
+        }
+        Ok(Listener {
+            sockets,
+            routes: RefCell::new(HashMap::new()),
+            next_socket: Cell::new(0),
+            traffic: Cell::new(Traffic::default())
+        })
+    }
+
+    pub fn send(&self, packet: Packet) -> Result<(), SendError<Packet>> {
+        // Send through the socket the client's packets arrive on. Before the
+        // first one, use the first socket of the client's address family, or
+        // the dual-stack socket if there is none
+        let mut traffic = self.traffic.get();
+        traffic.packets_out += 1;
+        traffic.bytes_out += packet.payload().len() as u64;
+        self.traffic.set(traffic);
+
+        let is_ipv4 = packet.addr().is_ipv4();
+        let index = self.routes.borrow().get(&packet.addr()).copied()
+            .or(self.sockets.iter().position(|(local, _, _)| local.is_ipv4() == is_ipv4))
+            .unwrap_or(0);
+        self.sockets[index].1.send(packet)
+    }
+
+    pub fn try_recv(&self) -> Option<SocketEvent> {
+        // Get the next event from any of the sockets, starting after the one
+        // that had the last event so a busy socket can't starve the others
+        let start = self.next_socket.get();
+        for offset in 0..self.sockets.len() {
+            let index = (start + offset) % self.sockets.len();
+            if let Ok(event) = self.sockets[index].2.try_recv() {
+                return self.received(index, event);
+            }
+        }
+        None
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SocketEvent> {
+        // Wait for the next event from any of the sockets, up to the timeout
+        let mut select = Select::new();
+        for (_, _, receiver) in &self.sockets {
+            select.recv(receiver);
+        }
+        let operation = select.select_timeout(timeout).ok()?;
+        let index = operation.index();
+        let event = operation.recv(&self.sockets[index].2).ok()?;
+        self.received(index, event)
+    }
+
+    pub fn traffic(&self) -> Traffic {
+        // Get the packets and bytes sent and received so far
+        self.traffic.get()
+    }
+
+    fn received(&self, index: usize, event: SocketEvent) -> Option<SocketEvent> {
+        // Remember which socket a client talks to and add its packets to the
+        // traffic. Clients that timed out are forgotten
+        self.next_socket.set(index + 1);
+        match &event {
+            SocketEvent::Packet(packet) => {
+                self.routes.borrow_mut().insert(packet.addr(), index);
+                let mut traffic = self.traffic.get();
+                traffic.packets_in += 1;
+                traffic.bytes_in += packet.payload().len() as u64;
+                self.traffic.set(traffic);
+            },
+            SocketEvent::Connect(address) => { self.routes.borrow_mut().insert(*address, index); },
+            SocketEvent::Timeout(address) => { self.routes.borrow_mut().remove(address); }
+        }
+        Some(event)
+    }
+}
@@ -1,21 +1,23 @@
+mod config;
+mod listener;
+mod matchmaking;
+mod metrics;
+mod rating;
 mod server;
+mod status;
+mod timing;
 
-use std::io;
-use std::io::Write;
 use laminar::ErrorKind;
 
 fn main() -> Result<(), ErrorKind> {
-    // Start the server
-    let stdin = io::stdin();
-
-    print!("Port the server should listen on (8080): ");
-    io::stdout().flush().unwrap();
-
-    let mut input = String::new();
-    stdin.read_line(&mut input)?;
-
-    server::server(match input.parse::<i32>() {
-        Ok(port) => port,
-        Err(_) => 8080
-    })
-}
\ No newline at end of file
+    // Read the settings and start the server
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    shared::logging::init(config.log.as_deref(), config.log_json);
+    server::server(config)
+}
@@ -0,0 +1,155 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+use log::info;
+
+// Rating gap allowed right away, and how much it widens per second of waiting
+const BASE_RATING_GAP: f64 = 100.0;
+const RATING_GAP_PER_SECOND: f64 = 25.0;
+
+pub struct QueueEntry {
+    pub address: SocketAddr,
+    pub identity: String,
+    pub color: [u8; 3],
+    pub rating: f64,
+    // Players without a name go by their address, which changes every run,
+    // so they play unrated
+    pub rated: bool,
+    pub joined: Instant
+}
+
+impl QueueEntry {
+    pub fn allowed_gap(&self, now: Instant) -> f64 {
+        // Get the rating gap this player accepts after waiting in the queue
+        let waited = now.duration_since(self.joined).as_secs_f64();
+        BASE_RATING_GAP + RATING_GAP_PER_SECOND * waited
+    }
+}
+
+pub struct MatchmakingQueue {
+    entries: Vec<QueueEntry>
+}
+
+impl MatchmakingQueue {
+    pub fn new() -> MatchmakingQueue {
+        MatchmakingQueue { entries: Vec::new() }
+    }
+
+    pub fn entries(&self) -> &[QueueEntry] {
+        // Get every player waiting, longest waiting first
+        &self.entries
+    }
+
+    pub fn contains(&self, address: &SocketAddr) -> bool {
+        // Check if a player is already waiting
+        self.entries.iter().any(|entry| entry.address == *address)
+    }
+
+    pub fn push(&mut self, entry: QueueEntry) {
+        // Add a player to the back of the queue
+        info!(player = entry.identity.as_str(), address:% = entry.address, rating = entry.rating; "Joined the queue");
+        self.entries.push(entry);
+    }
+
+    pub fn remove(&mut self, address: &SocketAddr) {
+        // Remove a player from the queue
+        self.entries.retain(|entry| entry.address != *address);
+    }
+
+    pub fn find_match(&mut self, group_size: usize, now: Instant) -> Option<Vec<QueueEntry>> {
+        // Find a group of players with similar ratings. The player waiting the
+        // longest goes first, and takes the closest ratings within their gap
+        for anchor in 0..self.entries.len() {
+            let anchor_rating = self.entries[anchor].rating;
+            let gap = self.entries[anchor].allowed_gap(now);
+
+            let mut candidates: Vec<usize> = (0..self.entries.len())
+                .filter(|&i| i != anchor && (self.entries[i].rating - anchor_rating).abs() <= gap)
+                .collect();
+            if candidates.len() + 1 < group_size {
+                continue;
+            }
+            candidates.sort_by(|&a, &b| {
+                let gap_a = (self.entries[a].rating - anchor_rating).abs();
+                let gap_b = (self.entries[b].rating - anchor_rating).abs();
+                gap_a.total_cmp(&gap_b)
+            });
+            candidates.truncate(group_size - 1);
+            candidates.push(anchor);
+
+            // Remove from the back so the remaining indices stay valid
+            candidates.sort_unstable_by(|a, b| b.cmp(a));
+            let mut group: Vec<QueueEntry> = candidates.into_iter()
+                .map(|i| self.entries.remove(i))
+                .collect();
+            group.sort_by_key(|entry| entry.joined);
+            return Some(group);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn entry(port: u16, rating: f64, joined: Instant) -> QueueEntry {
+        QueueEntry {
+            address: SocketAddr::from(([127, 0, 0, 1], port)),
+            identity: format!("player{}", port),
+            color: [0, 0, 0],
+            rating,
+            rated: true,
+            joined
+        }
+    }
+
+    fn ports(group: &[QueueEntry]) -> Vec<u16> {
+        group.iter().map(|entry| entry.address.port()).collect()
+    }
+
+    #[test]
+    fn close_ratings_are_matched() {
+        let now = Instant::now();
+        let mut queue = MatchmakingQueue::new();
+        queue.push(entry(1, 1200.0, now - Duration::from_millis(100)));
+        queue.push(entry(2, 1250.0, now));
+        let group = queue.find_match(2, now).unwrap();
+        assert_eq!(ports(&group), vec![1, 2]);
+        assert!(queue.entries().is_empty());
+    }
+
+    #[test]
+    fn distant_ratings_wait_until_the_gap_widens() {
+        let joined = Instant::now();
+        let mut queue = MatchmakingQueue::new();
+        queue.push(entry(1, 1000.0, joined));
+        queue.push(entry(2, 1200.0, joined));
+        assert!(queue.find_match(2, joined).is_none());
+        assert_eq!(queue.entries().len(), 2);
+        assert!(queue.find_match(2, joined + Duration::from_secs(4)).is_some());
+    }
+
+    #[test]
+    fn the_longest_waiting_player_takes_the_closest_ratings() {
+        let now = Instant::now();
+        let mut queue = MatchmakingQueue::new();
+        queue.push(entry(1, 1200.0, now - Duration::from_millis(300)));
+        queue.push(entry(2, 1290.0, now - Duration::from_millis(200)));
+        queue.push(entry(3, 1210.0, now - Duration::from_millis(100)));
+        queue.push(entry(4, 1220.0, now));
+        let group = queue.find_match(3, now).unwrap();
+        assert_eq!(ports(&group), vec![1, 3, 4]);
+        assert_eq!(ports(queue.entries()), vec![2]);
+    }
+
+    #[test]
+    fn too_few_players_are_not_matched() {
+        let now = Instant::now();
+        let mut queue = MatchmakingQueue::new();
+        queue.push(entry(1, 1200.0, now));
+        queue.push(entry(2, 1200.0, now));
+        assert!(queue.find_match(3, now).is_none());
+        assert_eq!(queue.entries().len(), 2);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use crate::listener::Traffic;
+use crate::timing::TimeStats;
+
+// How often the status report is written, the rates in it are averaged over
+// this long
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct ClientStats {
+    connected: Instant,
+    rtt_millis: Option<u16>,
+    first_heartbeat: Option<u32>,
+    last_heartbeat: u32,
+    heartbeats: u64
+}
+
+impl ClientStats {
+    fn new() -> ClientStats {
+        ClientStats { connected: Instant::now(), rtt_millis: None, first_heartbeat: None, last_heartbeat: 0, heartbeats: 0 }
+    }
+
+    pub fn record_heartbeat(&mut self, sequence: u32, rtt_millis: Option<u16>) {
+        // Count a heartbeat along with the round trip time the client measured
+        let first = *self.first_heartbeat.get_or_insert(sequence);
+        if sequence >= first {
+            self.last_heartbeat = self.last_heartbeat.max(sequence);
+            self.heartbeats += 1;
+        }
+        self.rtt_millis = rtt_millis.or(self.rtt_millis);
+    }
+
+    pub fn loss(&self) -> Option<f64> {
+        // Get the share of the client's heartbeats that never arrived, they
+        // are numbered so the missing ones are known
+        let first = self.first_heartbeat?;
+        let expected = (self.last_heartbeat - first) as u64 + 1;
+        Some(1.0 - self.heartbeats.min(expected) as f64 / expected as f64)
+    }
+}
+
+// What the status report shows of a match being played
+pub struct MatchStatus<'a> {
+    pub id: u32,
+    pub tick: u32,
+    pub players: usize,
+    pub jitter: &'a TimeStats
+}
+
+pub struct Metrics {
+    started: Instant,
+    timeouts: u64,
+    clients: HashMap<SocketAddr, ClientStats>,
+    // How long the ticks took to send since the last report
+    pub tick_durations: TimeStats,
+    last_report: Instant,
+    last_traffic: Traffic
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            started: Instant::now(),
+            timeouts: 0,
+            clients: HashMap::new(),
+            tick_durations: TimeStats::new(),
+            last_report: Instant::now(),
+            last_traffic: Traffic::default()
+        }
+    }
+
+    pub fn client(&mut self, address: SocketAddr) -> &mut ClientStats {
+        // Get the stats of a client, which counts as connected from its first packet
+        self.clients.entry(address).or_insert_with(ClientStats::new)
+    }
+
+    pub fn time_out(&mut self, address: SocketAddr) {
+        // Forget a client that stopped sending packets
+        self.clients.remove(&address);
+        self.timeouts += 1;
+    }
+
+    pub fn is_report_due(&self, now: Instant) -> bool {
+        now.duration_since(self.last_report) >= REPORT_INTERVAL
+    }
+
+    pub fn report(&mut self, now: Instant, traffic: Traffic, queued: usize, matches: &[MatchStatus],
+        rooms: &HashMap<SocketAddr, u32>) -> String {
+        // Write the stats as "name value" lines, followed by a line for every
+        // match and every client
+        let seconds = now.duration_since(self.last_report).as_secs_f64().max(0.001);
+        let rate = |current: u64, last: u64| (current - last) as f64 / seconds;
+
+        let mut report = String::new();
+        let _ = writeln!(report, "uptime_seconds {}", now.duration_since(self.started).as_secs());
+        let _ = writeln!(report, "connected_clients {}", self.clients.len());
+        let _ = writeln!(report, "queued_players {}", queued);
+        let _ = writeln!(report, "active_matches {}", matches.len());
+        let _ = writeln!(report, "timeouts {}", self.timeouts);
+        let _ = writeln!(report, "packets_in_per_second {:.1}", rate(traffic.packets_in, self.last_traffic.packets_in));
+        let _ = writeln!(report, "packets_out_per_second {:.1}", rate(traffic.packets_out, self.last_traffic.packets_out));
+        let _ = writeln!(report, "bytes_in_per_second {:.1}", rate(traffic.bytes_in, self.last_traffic.bytes_in));
+        let _ = writeln!(report, "bytes_out_per_second {:.1}", rate(traffic.bytes_out, self.last_traffic.bytes_out));
+        let _ = writeln!(report, "tick_duration_mean_ms {:.3}", self.tick_durations.mean());
+        let _ = writeln!(report, "tick_duration_max_ms {:.3}", self.tick_durations.max());
+
+        for status in matches {
+            let _ = writeln!(report, "match {} players={} tick={} jitter_mean_ms={:.2} jitter_deviation_ms={:.2} jitter_max_ms={:.2}",
+                status.id, status.players, status.tick, status.jitter.mean(), status.jitter.deviation(), status.jitter.max());
+        }
+        let mut addresses: Vec<&SocketAddr> = self.clients.keys().collect();
+        addresses.sort();
+        for address in addresses {
+            let client = &self.clients[address];
+            let room = rooms.get(address).map_or("-".to_owned(), |room_id| room_id.to_string());
+            let rtt = client.rtt_millis.map_or("-".to_owned(), |rtt| rtt.to_string());
+            let loss = client.loss().map_or("-".to_owned(), |loss| format!("{:.1}", loss * 100.0));
+            let _ = writeln!(report, "client {} match={} rtt_ms={} loss_percent={} connected_seconds={}",
+                address, room, rtt, loss, now.duration_since(client.connected).as_secs());
+        }
+
+        self.last_report = now;
+        self.last_traffic = traffic;
+        self.tick_durations = TimeStats::new();
+        report
+    }
+}
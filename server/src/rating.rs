@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fs;
+use log::{error, info, warn};
+
+pub const DEFAULT_RATING: f64 = 1200.0;
+const K_FACTOR: f64 = 32.0;
+
+pub struct Ratings {
+    ratings: HashMap<String, f64>,
+    path: String
+}
+
+impl Ratings {
+    pub fn load(path: &str) -> Ratings {
+        // Load the ratings from disk, starting fresh if there is no file yet.
+        // Ratings that are not a finite number are skipped
+        let mut ratings = HashMap::new();
+        if let Ok(contents) = fs::read_to_string(path) {
+            for line in contents.lines() {
+                if let Some((identity, rating)) = line.rsplit_once(' ') {
+                    match rating.parse::<f64>() {
+                        Ok(rating) if rating.is_finite() => { ratings.insert(identity.to_owned(), rating); },
+                        _ => warn!(player = identity; "Ignoring an invalid rating in {}", path)
+                    }
+                }
+            }
+        }
+        Ratings { ratings, path: path.to_owned() }
+    }
+
+    pub fn save(&self) {
+        // Write every rating to disk, one "identity rating" pair per line
+        let mut contents = String::new();
+        for (identity, rating) in self.ratings.iter() {
+            contents.push_str(&format!("{} {:.1}\n", identity, rating));
+        }
+        if let Err(error) = fs::write(&self.path, contents) {
+            error!(path = self.path.as_str(); "Failed to save ratings: {}", error);
+        }
+    }
+
+    pub fn get(&self, identity: &str) -> f64 {
+        // Get the rating of a player, new players start at the default rating
+        *self.ratings.get(identity).unwrap_or(&DEFAULT_RATING)
+    }
+
+    pub fn record(&mut self, scores: &[(String, f64)]) {
+        // Update the ratings from a finished match. Every player is compared
+        // against every other player, a higher score counts as a win
+        if scores.len() < 2 {
+            return;
+        }
+        let k = K_FACTOR / (scores.len() - 1) as f64;
+        let mut deltas = vec![0.0; scores.len()];
+        for i in 0..scores.len() {
+            for j in 0..scores.len() {
+                if i == j {
+                    continue;
+                }
+                let actual = if scores[i].1 > scores[j].1 {
+                    1.0
+                } else if scores[i].1 < scores[j].1 {
+                    0.0
+                } else {
+                    0.5
+                };
+                let expected = expected_score(self.get(&scores[i].0), self.get(&scores[j].0));
+                deltas[i] += k * (actual - expected);
+            }
+        }
+        for (i, (identity, _)) in scores.iter().enumerate() {
+            let rating = self.get(identity) + deltas[i];
+            info!(player = identity.as_str(), rating = rating, change = deltas[i]; "Rating updated");
+            self.ratings.insert(identity.clone(), rating);
+        }
+        self.save();
+    }
+}
+
+fn expected_score(rating: f64, opponent_rating: f64) -> f64 {
+    // Elo expected score of a player against an opponent
+    1.0 / (1.0 + 10f64.powf((opponent_rating - rating) / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
+
+    fn ratings(name: &str) -> Ratings {
+        let path = env::temp_dir().join(format!("snek-ratings-{}-{}.txt", name, std::process::id()));
+        Ratings { ratings: HashMap::new(), path: path.to_string_lossy().into_owned() }
+    }
+
+    fn scores(entries: &[(&str, f64)]) -> Vec<(String, f64)> {
+        entries.iter().map(|(identity, score)| (identity.to_string(), *score)).collect()
+    }
+
+    #[test]
+    fn equal_players_move_by_half_the_k_factor() {
+        let mut ratings = ratings("equal");
+        ratings.record(&scores(&[("a", 1.0), ("b", 0.0)]));
+        assert!((ratings.get("a") - (DEFAULT_RATING + K_FACTOR / 2.0)).abs() < 1e-9);
+        assert!((ratings.get("b") - (DEFAULT_RATING - K_FACTOR / 2.0)).abs() < 1e-9);
+        let _ = fs::remove_file(&ratings.path);
+    }
+
+    #[test]
+    fn a_tie_between_equals_changes_nothing() {
+        let mut ratings = ratings("tie");
+        ratings.record(&scores(&[("a", 0.5), ("b", 0.5)]));
+        assert!((ratings.get("a") - DEFAULT_RATING).abs() < 1e-9);
+        assert!((ratings.get("b") - DEFAULT_RATING).abs() < 1e-9);
+        let _ = fs::remove_file(&ratings.path);
+    }
+
+    #[test]
+    fn an_upset_moves_ratings_further() {
+        let mut ratings = ratings("upset");
+        ratings.ratings.insert("strong".to_owned(), 1600.0);
+        ratings.record(&scores(&[("strong", 0.0), ("weak", 1.0)]));
+        let gain = ratings.get("weak") - DEFAULT_RATING;
+        assert!(gain > K_FACTOR / 2.0 && gain < K_FACTOR);
+        assert!((ratings.get("strong") - (1600.0 - gain)).abs() < 1e-9);
+        let _ = fs::remove_file(&ratings.path);
+    }
+
+    #[test]
+    fn bigger_matches_keep_the_total_change_in_bounds() {
+        let mut ratings = ratings("four");
+        ratings.record(&scores(&[("a", 1.0), ("b", 0.0), ("c", 0.0), ("d", 0.0)]));
+        assert!((ratings.get("a") - (DEFAULT_RATING + K_FACTOR / 2.0)).abs() < 1e-9);
+        let total: f64 = ["a", "b", "c", "d"].iter().map(|identity| ratings.get(identity) - DEFAULT_RATING).sum();
+        assert!(total.abs() < 1e-9);
+        let _ = fs::remove_file(&ratings.path);
+    }
+
+    #[test]
+    fn a_single_player_is_not_rated() {
+        let mut ratings = ratings("single");
+        ratings.record(&scores(&[("a", 1.0)]));
+        assert!(ratings.ratings.is_empty());
+    }
+
+    #[test]
+    fn ratings_survive_a_reload_and_invalid_lines_are_skipped() {
+        let mut ratings = ratings("reload");
+        ratings.record(&scores(&[("first player", 1.0), ("b", 0.0)]));
+        let mut contents = fs::read_to_string(&ratings.path).unwrap();
+        contents.push_str("c NaN\nd inf\n");
+        fs::write(&ratings.path, contents).unwrap();
+        let loaded = Ratings::load(&ratings.path);
+        assert!((loaded.get("first player") - ratings.get("first player")).abs() < 0.1);
+        assert!((loaded.get("b") - ratings.get("b")).abs() < 0.1);
+        assert!(!loaded.ratings.contains_key("c"));
+        assert!(!loaded.ratings.contains_key("d"));
+        let _ = fs::remove_file(&ratings.path);
+    }
+}
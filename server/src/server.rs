@@ -1,20 +1,79 @@
-use core::time;
-use std::{thread, time::Instant};
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use crossbeam_channel::Sender;
-use laminar::{ErrorKind, Packet, Socket, SocketEvent, Config};
-use shared::{SnekId, MessageType, GameResult, INVALID_ID, MAGIC_BYTE, MAX_PLAYERS};
+use laminar::{ErrorKind, Packet, SocketEvent, Config};
+use log::{debug, info, trace, warn};
+use shared::{
+    Delivery, Direction, SnekId, TeamId, MessageType, GameResult, MatchSettings,
+    COUNTDOWN_SECONDS, DEFAULT_COLOR, INVALID_ID, MAGIC_BYTE, MAX_NAME_LENGTH, MAX_PLAYERS, NO_TEAM
+};
+use crate::config::ServerConfig;
+use crate::listener::{Listener, Traffic};
+use crate::matchmaking::{MatchmakingQueue, QueueEntry};
+use crate::metrics::{MatchStatus, Metrics};
+use crate::rating::{Ratings, DEFAULT_RATING};
+use crate::status::StatusPage;
+use crate::timing::TickTimer;
 
-struct ServerState {
+const RATINGS_FILE: &str = "ratings.txt";
+// Most ticks resent to a client that has fallen behind, in one packet
+const REDUNDANT_TICKS: u32 = 16;
+// Longest the server sleeps between checks on the countdowns and the queue
+const SERVICE_INTERVAL: Duration = Duration::from_millis(20);
+
+type RoomId = u32;
+
+struct Room {
     pub snek_ids: Vec<SnekId>,
     pub address_to_id: HashMap<SocketAddr, SnekId>,
     pub id_to_address: HashMap<SnekId, SocketAddr>,
+    pub identities: HashMap<SnekId, String>,
+    pub rated: Vec<SnekId>,
+    pub colors: HashMap<SnekId, [u8; 3]>,
     pub moves: HashMap<SnekId, u8>,
-    pub game_started: bool
+    // The moves of every tick sent so far, and the last tick each client
+    // confirmed, so lost ticks are sent again with the next ones
+    pub history: Vec<Vec<u8>>,
+    pub confirmed_ticks: HashMap<SnekId, u32>,
+    // Sneks not known to be out, less the deaths reported and the players
+    // that left, whose sneks forfeit
+    pub alive: Vec<SnekId>,
+    pub settings: MatchSettings,
+    pub game_started: bool,
+    pub countdown: u8,
+    pub countdown_started: Instant,
+    pub tick: u32,
+    pub timer: TickTimer,
+    pub started_at: Instant,
+    pub remaining: u16,
+    pub time_up: bool
 }
 
-impl ServerState {
+impl Room {
+    pub fn new(settings: MatchSettings) -> Room {
+        Room {
+            snek_ids: Vec::new(),
+            address_to_id: HashMap::new(),
+            id_to_address: HashMap::new(),
+            identities: HashMap::new(),
+            rated: Vec::new(),
+            colors: HashMap::new(),
+            moves: HashMap::new(),
+            history: Vec::new(),
+            confirmed_ticks: HashMap::new(),
+            alive: Vec::new(),
+            settings,
+            game_started: false,
+            countdown: 0,
+            countdown_started: Instant::now(),
+            tick: 0,
+            timer: TickTimer::new(settings.tick_millis),
+            started_at: Instant::now(),
+            remaining: settings.duration,
+            time_up: false
+        }
+    }
+
     pub fn get_snek_count(&self) -> usize {
         // Get the number of sneks
         self.snek_ids.len()
@@ -36,179 +95,485 @@ impl ServerState {
         self.id_to_address.insert(id, address);
     }
 
+    pub fn start_countdown(&mut self) {
+        // Count down to the start of the game, moves sent meanwhile are
+        // played on the first tick
+        self.countdown = COUNTDOWN_SECONDS;
+        self.countdown_started = Instant::now();
+    }
+
     pub fn start_game(&mut self) {
-        // Update the game state to started
+        // Update the game state to started and start the clock. The first
+        // tick is due right away
         self.game_started = true;
+        self.started_at = Instant::now();
+        self.timer = TickTimer::new(self.settings.tick_millis);
     }
 
     pub fn end_game(&mut self) {
         // Update the game state to ended
         self.game_started = false;
     }
+
+    pub fn get_result(&self, id: SnekId, winner: SnekId, winning_team: TeamId) -> GameResult {
+        // Get the result of the game for the specified snek
+        if winner == INVALID_ID && winning_team == NO_TEAM {
+            GameResult::Tie
+        } else if id == winner || (winning_team != NO_TEAM && self.settings.team_of(id) == winning_team) {
+            GameResult::Win
+        } else {
+            GameResult::Loss
+        }
+    }
+
+    pub fn send_ticks(&self, sender: &Listener) {
+        // Send every client the ticks it has not confirmed yet, oldest first
+        for (&snek_address, snek_id) in self.address_to_id.iter() {
+            let first_tick = self.confirmed_ticks[snek_id] + 1;
+            if first_tick > self.tick {
+                continue;
+            }
+            let last_tick = self.tick.min(first_tick + REDUNDANT_TICKS - 1);
+            let mut payload = first_tick.to_be_bytes().to_vec();
+            for moves in &self.history[first_tick as usize - 1..last_tick as usize] {
+                payload.extend(moves.iter());
+            }
+            send_packet(MessageType::MoveEvent, payload, snek_address, sender);
+        }
+    }
+
+    pub fn get_players_payload(&self) -> Vec<u8> {
+        // Serialize the id, color and name of every snek for the BroadcastIdsEvent
+        let mut payload = Vec::new();
+        for id in &self.snek_ids {
+            let name = self.identities[id].as_bytes();
+            payload.push(*id);
+            payload.extend(self.colors[id].iter());
+            payload.push(name.len() as u8);
+            payload.extend(name.iter());
+        }
+        payload
+    }
+}
+
+struct ServerState {
+    pub rooms: HashMap<RoomId, Room>,
+    pub address_to_room: HashMap<SocketAddr, RoomId>,
+    pub queue: MatchmakingQueue,
+    pub ratings: Ratings,
+    pub settings: MatchSettings,
+    pub metrics: Metrics,
+    next_room_id: RoomId
+}
+
+impl ServerState {
+    pub fn open_room(&mut self, players: Vec<QueueEntry>, sender: &Listener) {
+        // Put a matched group of players into a new room and start their game
+        let room_id = self.next_room_id;
+        self.next_room_id += 1;
+
+        // Every client seeds its pickup spawns with the same value
+        let settings = MatchSettings { seed: rand::random(), ..self.settings };
+
+        let mut room = Room::new(settings);
+        for player in players {
+            // Assign the client an ID and link it to their address
+            let id = room.get_next_id();
+            room.snek_ids.push(id);
+            room.alive.push(id);
+            room.link_snek(player.address, id);
+            room.moves.insert(id, Direction::Invalid as u8);
+            room.confirmed_ticks.insert(id, 0);
+            info!(player = player.identity.as_str(), address:% = player.address, room = room_id, snek = id; "Joined a room");
+            if player.rated {
+                room.rated.push(id);
+            }
+            room.identities.insert(id, player.identity);
+            room.colors.insert(id, player.color);
+            self.address_to_room.insert(player.address, room_id);
+
+            send_packet(MessageType::AssignIdEvent, vec![id], player.address, sender);
+        }
+        room.start_countdown();
+        info!(room = room_id, seconds = room.countdown; "Countdown started");
+
+        // Broadcast IDs & game start event, the sneks hold still until the
+        // countdown ends
+        for (&snek_address, _) in room.address_to_id.iter() {
+            send_packet(MessageType::BroadcastIdsEvent, room.get_players_payload(), snek_address, sender);
+            send_packet(MessageType::StartEvent, settings.encode(), snek_address, sender);
+            send_packet(MessageType::CountdownEvent, vec![room.countdown], snek_address, sender);
+        }
+        self.rooms.insert(room_id, room);
+    }
+
+    pub fn end_room(&mut self, room_id: RoomId, winner: SnekId, winning_team: TeamId, sender: &Listener) {
+        // Broadcast game end event to the room, then close it
+        let room = self.rooms.get_mut(&room_id).unwrap();
+        for (&snek_address, &snek_id) in room.address_to_id.iter() {
+            let result = room.get_result(snek_id, winner, winning_team);
+            send_packet(MessageType::EndEvent,
+                vec![result as u8, winner, winning_team], snek_address, sender);
+        }
+        room.end_game();
+        info!(room = room_id, winner = winner, team = winning_team, tick = room.tick; "Match ended");
+        self.close_room(room_id, winner, winning_team);
+    }
+
+    pub fn leave_room(&mut self, address: SocketAddr, sender: &Listener) {
+        // Take a client that timed out out of its room. Its snek forfeits, so
+        // the match ends once a single snek or team of connected players is
+        // left, and a room nobody is left in is closed unrated
+        let room_id = match self.address_to_room.remove(&address) {
+            Some(room_id) => room_id,
+            None => return
+        };
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return
+        };
+        let id = match room.address_to_id.remove(&address) {
+            Some(id) => id,
+            None => return
+        };
+        room.id_to_address.remove(&id);
+        room.alive.retain(|&alive| alive != id);
+        if room.address_to_id.is_empty() {
+            self.rooms.remove(&room_id);
+            info!(room = room_id; "Room closed, every player left");
+        } else if let Some((winner, winning_team)) = room.settings.get_winner(&room.alive) {
+            info!(room = room_id, snek = id; "Snek forfeited");
+            self.end_room(room_id, winner, winning_team, sender);
+        }
+    }
+
+    pub fn next_deadline(&self) -> Instant {
+        // Get when the next tick of a running game is due, or when to check on
+        // the countdowns and the queue if that comes first
+        self.rooms.values()
+            .filter(|room| room.game_started)
+            .map(|room| room.timer.next_tick())
+            .fold(Instant::now() + SERVICE_INTERVAL, Instant::min)
+    }
+
+    pub fn status_report(&mut self, now: Instant, traffic: Traffic) -> String {
+        // Describe the server's load and every match and client for the status page
+        let mut matches: Vec<MatchStatus> = self.rooms.iter()
+            .map(|(&id, room)| MatchStatus { id, tick: room.tick, players: room.snek_ids.len(), jitter: &room.timer.jitter })
+            .collect();
+        matches.sort_by_key(|status| status.id);
+        self.metrics.report(now, traffic, self.queue.entries().len(), &matches, &self.address_to_room)
+    }
+
+    pub fn broadcast_lobby(&self, sender: &Listener) {
+        // Send the names of everyone waiting for a match to the waiting players
+        let mut payload = Vec::new();
+        for entry in self.queue.entries() {
+            payload.push(entry.identity.len() as u8);
+            payload.extend(entry.identity.as_bytes());
+        }
+        for entry in self.queue.entries() {
+            send_packet(MessageType::LobbyEvent, payload.clone(), entry.address, sender);
+        }
+    }
+
+    pub fn close_room(&mut self, room_id: RoomId, winner: SnekId, winning_team: TeamId) {
+        // Rate the named players of a finished room and remove it
+        let room = match self.rooms.remove(&room_id) {
+            Some(room) => room,
+            None => return
+        };
+        let scores: Vec<(String, f64)> = room.rated.iter()
+            .map(|id| {
+                let score = match room.get_result(*id, winner, winning_team) {
+                    GameResult::Win => 1.0,
+                    GameResult::Tie => 0.5,
+                    GameResult::Loss => 0.0
+                };
+                (room.identities[id].clone(), score)
+            })
+            .collect();
+        self.ratings.record(&scores);
+        let jitter = &room.timer.jitter;
+        info!(room = room_id, ticks = jitter.count(), late_mean_ms = jitter.mean(), late_deviation_ms = jitter.deviation(),
+            late_max_ms = jitter.max(); "Room closed");
+        for address in room.address_to_id.keys() {
+            self.address_to_room.remove(address);
+        }
+    }
 }
 
-fn send_packet(message_type: MessageType, payload: Vec<u8>, address: SocketAddr, sender: &Sender<Packet>) {
+fn send_packet(message_type: MessageType, payload: Vec<u8>, address: SocketAddr, sender: &Listener) {
 	// Server sends packets to client
     let mut actual_payload = vec![MAGIC_BYTE, message_type as u8];
     actual_payload.extend(payload.iter());
-    sender.send(Packet::reliable_sequenced(address, actual_payload, Some(0))).unwrap()
+    let (delivery, stream) = message_type.delivery();
+    let packet = match delivery {
+        Delivery::ReliableOrdered => Packet::reliable_ordered(address, actual_payload, Some(stream as u8)),
+        Delivery::UnreliableSequenced => Packet::unreliable_sequenced(address, actual_payload, Some(stream as u8)),
+        Delivery::Unreliable => Packet::unreliable(address, actual_payload)
+    };
+    sender.send(packet).unwrap()
 }
 
-fn handle_packet(packet: &Packet, sender: &Sender<Packet>, state: &mut ServerState) {
+fn handle_packet(packet: &Packet, sender: &Listener, state: &mut ServerState) {
     // Server receives packets from client
     let address = packet.addr();
     let payload = packet.payload();
-    // println!("{:?}", payload);
-    let magic_byte = payload[0];
-    if magic_byte != MAGIC_BYTE {
+    if payload.len() < 2 || payload[0] != MAGIC_BYTE {
+        warn!(address:% = address, bytes = payload.len(); "Ignoring a packet that is not from a Snek client");
         return;
     }
     let message_type = payload[1];
     let received_data = &payload[2..];
-    // let payload_str = String::from_utf8_lossy(packet.payload());
-    // let message = payload_str.as_ref();
+    trace!(address:% = address, kind = message_type, bytes = received_data.len(); "Received packet");
+    state.metrics.client(address);
     // Handles game events
     match message_type {
-        // New snek joins the game
+        // New snek joins the matchmaking queue
         x if x == MessageType::JoinEvent as u8 => {
-            // Only allow a maximum of MAX_PLAYERS clients to join the game
-            if state.get_snek_count() == MAX_PLAYERS { return }
-            if state.address_to_id.contains_key(&address) { return }
-
-            // Assign the client an ID and link it to their address
-            let id: u8 = state.get_next_id();
-            state.snek_ids.push(id);
-            state.link_snek(address, id);
-            state.moves.insert(id, 42);
-            println!("Snek with ID {} joined", id);
+            if state.address_to_room.contains_key(&address) { return }
+            if state.queue.contains(&address) { return }
 
-            println!("Sending ID {} back to snek...", id);
-
-            send_packet(MessageType::AssignIdEvent, vec![id], address, sender);
-            if state.get_snek_count() == MAX_PLAYERS {
-                // If MAX_PLAYERS clients have joined, start the game
-                state.start_game();
-
-                println!("Game started!");
-
-                // Broadcast IDs & game start event
-                for (&snek_address, _) in state.address_to_id.iter() {
-                    send_packet(MessageType::BroadcastIdsEvent, state.snek_ids.clone(), snek_address, sender);
-                    send_packet(MessageType::StartEvent, vec![], snek_address, sender);
-                }
-            }
+            // Players pick their color, and are identified by the name they
+            // send or their address. Control characters are left out of names
+            // so they cannot break lines in the ratings file
+            let (color, name) = if received_data.len() >= 3 {
+                ([received_data[0], received_data[1], received_data[2]], &received_data[3..])
+            } else {
+                (DEFAULT_COLOR, &received_data[0..0])
+            };
+            let name: String = String::from_utf8_lossy(name).chars().filter(|character| !character.is_control()).collect();
+            let (identity, rated) = match name.trim() {
+                "" => (address.to_string(), false),
+                name => (name.chars().take(MAX_NAME_LENGTH).collect(), true)
+            };
+            let rating = if rated { state.ratings.get(&identity) } else { DEFAULT_RATING };
+            state.queue.push(QueueEntry { address, identity, color, rating, rated, joined: Instant::now() });
+            state.broadcast_lobby(sender);
+        },
+        // Snek stops waiting for a match
+        x if x == MessageType::LeaveEvent as u8 => {
+            if !state.queue.contains(&address) { return }
+            info!(address:% = address; "Left the queue");
+            state.queue.remove(&address);
+            state.broadcast_lobby(sender);
         },
         // Send heartbeat
         x if x == MessageType::Heartbeat as u8 => {
-            // Send a heartbeat back to the client to prevent timing out
-            send_packet(MessageType::Heartbeat, vec![], address, sender);
+            // Send a heartbeat back to the client to prevent timing out, echoing
+            // the client's timestamp so it can measure its ping
+            if received_data.len() < 14 {
+                return;
+            }
+            send_packet(MessageType::Heartbeat, received_data[..8].to_vec(), address, sender);
+            let sequence = u32::from_be_bytes([received_data[8], received_data[9], received_data[10], received_data[11]]);
+            let rtt_millis = match u16::from_be_bytes([received_data[12], received_data[13]]) {
+                u16::MAX => None,
+                rtt_millis => Some(rtt_millis)
+            };
+            state.metrics.client(address).record_heartbeat(sequence, rtt_millis);
         },
         // Snek moves
         x if x == MessageType::MoveEvent as u8 => {
-            // Receive moves from both clients. Once both are received send
-            // them to the clients so they can update their game state simultaneously
+            // Remember the latest move of the snek, the next tick sends it to
+            // every client so they update their game state simultaneously.
+            // Clients send their move every tick along with the last tick
+            // they confirmed
+            let room = match state.address_to_room.get(&address) {
+                Some(room_id) => state.rooms.get_mut(room_id).unwrap(),
+                None => return
+            };
 
-            // println!("Received message {}", msg);
-            let origin_snek_id = state.address_to_id[&address];
+            if received_data.len() < 6 {
+                warn!(address:% = address, bytes = received_data.len(); "Ignoring a truncated move");
+                return;
+            }
+            let origin_snek_id = room.address_to_id[&address];
             if origin_snek_id != received_data[0] {
-                println!("Snek ID movement mismatch!");
+                warn!(address:% = address, snek = origin_snek_id, claimed = received_data[0]; "Ignoring a move for another snek");
                 return;
             }
-            // println!("Received move from {}", origin_snek_id);
-            *state.moves.get_mut(&origin_snek_id).unwrap() = received_data[1];
-            // println!("updating move");
-            // Only one move has been received, send heartbeat to prevent timing out
-            // and to wait for the second move
-            send_packet(MessageType::Heartbeat, vec![], address, sender);
+            trace!(snek = origin_snek_id, direction = received_data[1]; "Received move");
+            *room.moves.get_mut(&origin_snek_id).unwrap() = received_data[1];
+            let confirmed_tick = u32::from_be_bytes([received_data[2], received_data[3], received_data[4], received_data[5]]);
+            room.confirmed_ticks.insert(origin_snek_id, confirmed_tick.min(room.tick));
         },
         // snek death
         x if x == MessageType::DeathEvent as u8 => {
-            println!("Death event");
-            if !state.game_started {
+            let room_id = match state.address_to_room.get(&address) {
+                Some(&room_id) => room_id,
+                None => return
+            };
+            let room = state.rooms.get_mut(&room_id).unwrap();
+            if !room.game_started {
                 return;
             }
-            // let origin_snek_id = state.address_to_id[&address];
-            // Broadcast game end event
-            match received_data.len() {
-                // Tie
-                0 => {
-                    for (&snek_address, _) in state.address_to_id.iter() {
-                        send_packet(MessageType::EndEvent, 
-                            vec![GameResult::Tie as u8, INVALID_ID], snek_address, sender);
-                    }
-                    state.end_game();
-                },
-                // Winner
-                1 => {
-                    let winner = received_data[0];
-                    for (&snek_address, &snek_id) in state.address_to_id.iter() {
-                        if snek_id == winner {
-                            send_packet(MessageType::EndEvent, 
-                                vec![GameResult::Win as u8, winner], snek_address, sender);
-                        } else {
-                            send_packet(MessageType::EndEvent, 
-                                vec![GameResult::Loss as u8, winner], snek_address, sender);
-                        }
-                    }
-                    state.end_game();
-                },
-                _ => {
-                    for &snek_id in received_data {
-                        println!("Snek ID {} is alive", snek_id);
-                    }
-                }
+            // The game ends once a single snek, or a single team, is left.
+            // Sneks of players that left stay out even if the client still
+            // sees them moving
+            let alive: Vec<SnekId> = received_data.iter().copied().filter(|id| room.alive.contains(id)).collect();
+            debug!(room = room_id, snek = room.address_to_id[&address], alive:? = alive; "Snek died");
+            room.alive = alive;
+            let (winner, winning_team) = match room.settings.get_winner(&room.alive) {
+                Some(result) => result,
+                None => return
+            };
+            state.end_room(room_id, winner, winning_team, sender);
+        },
+        // Standings reported once the time has run out
+        x if x == MessageType::ScoreEvent as u8 => {
+            let room_id = match state.address_to_room.get(&address) {
+                Some(&room_id) => room_id,
+                None => return
+            };
+            let room = &state.rooms[&room_id];
+            if !room.game_started || !room.time_up {
+                return;
             }
+            // Sneks of players that left forfeit however long they grew
+            let scores: Vec<u8> = received_data.chunks_exact(7)
+                .filter(|entry| room.id_to_address.contains_key(&entry[0]))
+                .flatten()
+                .copied()
+                .collect();
+            let (winner, winning_team) = room.settings.get_timed_winner(&scores);
+            state.end_room(room_id, winner, winning_team, sender);
         }
-        _ => {}
+        _ => warn!(address:% = address, kind = message_type; "Ignoring a packet of unknown kind")
     }
 }
 
-pub fn server(port: i32) -> Result<(), ErrorKind> {
+pub fn server(config: ServerConfig) -> Result<(), ErrorKind> {
     // Establish the server socket
-    let mut config = Config::default();
-    config.socket_event_buffer_size = 100;
-    let mut socket = Socket::bind_with_config(format!("{}:{}", "127.0.0.1", port.to_string()), config)?;
-    println!("Server is listening on port {}", port);
-
-    let (sender, receiver) = (
-        socket.get_packet_sender(), socket.get_event_receiver());
-    let _thread = thread::spawn(move || socket.start_polling());
+    let socket_config = Config {
+        socket_event_buffer_size: 100,
+        idle_connection_timeout: config.timeout,
+        heartbeat_interval: Some(config.heartbeat),
+        ..Config::default()
+    };
+    let sender = Listener::bind(&config.addresses, config.port, socket_config)?;
 
     let mut state = ServerState {
-        snek_ids: Vec::new(),
-        address_to_id: HashMap::new(),
-        id_to_address: HashMap::new(),
-        moves: HashMap::new(),
-        game_started: false
+        rooms: HashMap::new(),
+        address_to_room: HashMap::new(),
+        queue: MatchmakingQueue::new(),
+        ratings: Ratings::load(RATINGS_FILE),
+        settings: config.settings,
+        metrics: Metrics::new(),
+        next_room_id: 1
+    };
+    let status = match config.status_port {
+        Some(port) => Some(StatusPage::serve(port)?),
+        None => None
     };
 
-    let mut now = Instant::now();
-    'outer: loop {
-        if let Ok(event) = receiver.try_recv() {
-            match event {
-                SocketEvent::Packet(packet) => handle_packet(&packet, &sender, &mut state),
-                SocketEvent::Timeout(address) => {
-                    println!("Client timed out: {}", address);
+    loop {
+        // Sleep until a packet arrives or the next tick is due, then handle
+        // the packets waiting. A flood of packets stops being drained once the
+        // tick is due so it cannot hold the tick back, the rest wait for the
+        // next cycle
+        let deadline = state.next_deadline();
+        let mut event = sender.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        while let Some(received) = event {
+            handle_event(received, &sender, &mut state);
+            event = if Instant::now() < deadline { sender.try_recv() } else { None };
+        }
+
+        // Group waiting players of similar rating into new rooms
+        let group_size = (state.settings.player_count as usize).min(MAX_PLAYERS);
+        while let Some(players) = state.queue.find_match(group_size, Instant::now()) {
+            state.open_room(players, &sender);
+            state.broadcast_lobby(&sender);
+        }
+
+        let now = Instant::now();
+        for (room_id, room) in state.rooms.iter_mut() {
+            // Broadcast the countdown every second, the game starts when it hits 0
+            if room.countdown > 0 {
+                let elapsed = room.countdown_started.elapsed().as_secs() as u8;
+                let countdown = COUNTDOWN_SECONDS.saturating_sub(elapsed);
+                if countdown != room.countdown {
+                    room.countdown = countdown;
+                    for &snek_address in room.address_to_id.keys() {
+                        send_packet(MessageType::CountdownEvent, vec![countdown], snek_address, &sender);
+                    }
+                    if countdown == 0 {
+                        room.start_game();
+                        info!(room = *room_id; "Match started");
+                    }
                 }
-                _ => {}
             }
-        }
-        if state.game_started && now.elapsed().as_millis() >= 120 {
-            // Send move to all other players
-            for (&snek_address, _) in state.address_to_id.iter() {
-                let mut payload = Vec::new();
-                for (&origin_snek_id, &sent_move) in state.moves.iter() {
-                    if sent_move == 42 {
-                        now = Instant::now();
-                        continue 'outer;
+            // Broadcast the remaining time of timed matches every second
+            if room.game_started && room.settings.is_timed() && !room.time_up {
+                let elapsed = room.started_at.elapsed().as_secs() as u16;
+                let remaining = room.settings.duration.saturating_sub(elapsed);
+                if remaining != room.remaining {
+                    room.remaining = remaining;
+                    // Stop ticking and wait for the clients to report the
+                    // standings, which they do once they played the last tick
+                    let mut payload = remaining.to_be_bytes().to_vec();
+                    if remaining == 0 {
+                        info!(room = *room_id, tick = room.tick; "Time is up");
+                        room.time_up = true;
+                        payload.extend(room.tick.to_be_bytes().iter());
                     }
-                    payload.push(origin_snek_id);
-                    payload.push(sent_move);
+                    for &snek_address in room.address_to_id.keys() {
+                        send_packet(MessageType::TimeEvent, payload.clone(), snek_address, &sender);
+                    }
+                }
+            }
+            if !room.game_started || !room.timer.poll(now) {
+                continue;
+            }
+            // Once the time is up no more ticks are played, but the ones a
+            // client has not confirmed are still sent until it does
+            if room.time_up {
+                room.send_ticks(&sender);
+                continue;
+            }
+            // Send move to all other players, numbered so every client plays
+            // the same tick. The tick never waits for a slow player, whose
+            // snek keeps the last direction they sent, and sneks yet to send
+            // one keep heading the way they spawned
+            let mut moves = vec![0];
+            for (&origin_snek_id, &sent_move) in room.moves.iter() {
+                if sent_move == Direction::Invalid as u8 {
+                    continue;
                 }
-                send_packet(MessageType::MoveEvent, payload, snek_address, &sender);
+                moves[0] += 1;
+                moves.push(origin_snek_id);
+                moves.push(sent_move);
+            }
+            room.history.push(moves);
+            room.tick += 1;
+
+            let tick_started = Instant::now();
+            room.send_ticks(&sender);
+            state.metrics.tick_durations.record(tick_started.elapsed());
+        }
+
+        if let Some(status) = &status {
+            if state.metrics.is_report_due(now) {
+                status.update(state.status_report(now, sender.traffic()));
             }
-            now = Instant::now();
         }
-        std::thread::sleep(time::Duration::from_millis(100));
+    }
+}
+
+fn handle_event(event: SocketEvent, sender: &Listener, state: &mut ServerState) {
+    // Handle a packet or a client timing out
+    match event {
+        SocketEvent::Packet(packet) => handle_packet(&packet, sender, state),
+        SocketEvent::Timeout(address) => {
+            info!(address:% = address, room:? = state.address_to_room.get(&address); "Client timed out");
+            state.metrics.time_out(address);
+            if state.queue.contains(&address) {
+                state.queue.remove(&address);
+                state.broadcast_lobby(sender);
+            }
+            state.leave_room(address, sender);
+        }
+        _ => {}
     }
 }
@@ -0,0 +1,46 @@
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+
+// Serves the latest status report as plain text to any HTTP request. It runs
+// on a thread of its own so a slow reader never holds up the game
+pub struct StatusPage {
+    report: Arc<Mutex<String>>
+}
+
+impl StatusPage {
+    pub fn serve(port: u16) -> io::Result<StatusPage> {
+        // Listen on this machine only, the report lists the players' addresses
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        info!(address:% = listener.local_addr()?; "Serving the status page");
+
+        let report = Arc::new(Mutex::new(String::new()));
+        let served = Arc::clone(&report);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let report = served.lock().unwrap().clone();
+                if let Err(error) = respond(stream, &report) {
+                    warn!("Status request failed: {}", error);
+                }
+            }
+        });
+        Ok(StatusPage { report })
+    }
+
+    pub fn update(&self, report: String) {
+        // Replace the report served from now on
+        *self.report.lock().unwrap() = report;
+    }
+}
+
+fn respond(mut stream: TcpStream, report: &str) -> io::Result<()> {
+    // Read the request, whatever it asks for, and answer with the report
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        report.len(), report)
+}
@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant};
+
+// Ticks more than this many periods behind are dropped instead of being
+// sent in a burst, after the server was stalled
+const MAX_TICKS_BEHIND: u32 = 2;
+
+pub struct TickTimer {
+    period: Duration,
+    next: Instant,
+    pub jitter: TimeStats
+}
+
+impl TickTimer {
+    pub fn new(tick_millis: u16) -> TickTimer {
+        // The first tick is due right away
+        TickTimer {
+            period: Duration::from_millis(tick_millis as u64),
+            next: Instant::now(),
+            jitter: TimeStats::new()
+        }
+    }
+
+    pub fn next_tick(&self) -> Instant {
+        // Get when the next tick is due
+        self.next
+    }
+
+    pub fn poll(&mut self, now: Instant) -> bool {
+        // Check if a tick is due, and schedule the one after it. Ticks are
+        // scheduled from when they were due rather than when they ran, so
+        // lateness does not add up over a match
+        if now < self.next {
+            return false;
+        }
+        let late = now - self.next;
+        self.jitter.record(late);
+        self.next += self.period;
+        if late > self.period * MAX_TICKS_BEHIND {
+            self.next = now + self.period;
+        }
+        true
+    }
+}
+
+// Running statistics of a duration, such as how late ticks ran
+pub struct TimeStats {
+    count: u64,
+    total: f64,
+    total_squared: f64,
+    max: f64
+}
+
+impl TimeStats {
+    pub fn new() -> TimeStats {
+        TimeStats { count: 0, total: 0.0, total_squared: 0.0, max: 0.0 }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        // Add a measured duration
+        let millis = duration.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.total += millis;
+        self.total_squared += millis * millis;
+        self.max = self.max.max(millis);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        // Get the average in milliseconds
+        if self.count == 0 { 0.0 } else { self.total / self.count as f64 }
+    }
+
+    pub fn deviation(&self) -> f64 {
+        // Get the standard deviation in milliseconds
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.total_squared / self.count as f64 - mean * mean).max(0.0).sqrt()
+    }
+
+    pub fn max(&self) -> f64 {
+        // Get the longest duration in milliseconds
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_are_due_one_period_apart() {
+        let mut timer = TickTimer::new(100);
+        let start = timer.next_tick();
+        assert!(timer.poll(start));
+        assert!(!timer.poll(start + Duration::from_millis(99)));
+        assert!(timer.poll(start + Duration::from_millis(100)));
+        assert_eq!(timer.next_tick(), start + Duration::from_millis(200));
+    }
+
+    #[test]
+    fn late_ticks_do_not_push_back_the_schedule() {
+        let mut timer = TickTimer::new(100);
+        let start = timer.next_tick();
+        assert!(timer.poll(start + Duration::from_millis(30)));
+        assert_eq!(timer.next_tick(), start + Duration::from_millis(100));
+        assert!(timer.poll(start + Duration::from_millis(120)));
+        assert_eq!(timer.next_tick(), start + Duration::from_millis(200));
+    }
+
+    #[test]
+    fn a_short_stall_catches_up() {
+        let mut timer = TickTimer::new(100);
+        let start = timer.next_tick();
+        assert!(timer.poll(start));
+        let now = start + Duration::from_millis(250);
+        assert!(timer.poll(now));
+        assert!(timer.poll(now));
+        assert!(!timer.poll(now));
+    }
+
+    #[test]
+    fn a_long_stall_drops_the_missed_ticks() {
+        let mut timer = TickTimer::new(100);
+        let start = timer.next_tick();
+        assert!(timer.poll(start));
+        let now = start + Duration::from_millis(1000);
+        assert!(timer.poll(now));
+        assert!(!timer.poll(now));
+        assert_eq!(timer.next_tick(), now + Duration::from_millis(100));
+    }
+
+    #[test]
+    fn lateness_is_recorded() {
+        let mut timer = TickTimer::new(100);
+        let start = timer.next_tick();
+        timer.poll(start + Duration::from_millis(10));
+        timer.poll(start + Duration::from_millis(130));
+        assert_eq!(timer.jitter.count(), 2);
+        assert!((timer.jitter.mean() - 20.0).abs() < 1e-9);
+        assert!((timer.jitter.max() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stats_of_nothing_are_zero() {
+        let stats = TimeStats::new();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.mean(), 0.0);
+        assert_eq!(stats.deviation(), 0.0);
+        assert_eq!(stats.max(), 0.0);
+    }
+
+    #[test]
+    fn stats_give_the_mean_deviation_and_max() {
+        let mut stats = TimeStats::new();
+        for millis in [10, 20, 30] {
+            stats.record(Duration::from_millis(millis));
+        }
+        assert_eq!(stats.count(), 3);
+        assert!((stats.mean() - 20.0).abs() < 1e-9);
+        assert!((stats.deviation() - (200.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert!((stats.max() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equal_durations_have_no_deviation() {
+        let mut stats = TimeStats::new();
+        for _ in 0..5 {
+            stats.record(Duration::from_millis(7));
+        }
+        assert!(stats.deviation() < 1e-6);
+    }
+}
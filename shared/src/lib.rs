@@ -1,3 +1,8 @@
+pub mod logging;
+mod settings;
+
+pub use settings::{GameMode, MatchSettings};
+
 pub type Coord = (i32, i32);
 
 #[derive(Copy, Clone, PartialEq)]
@@ -11,6 +16,17 @@ pub enum Direction {
 
 pub type SnekId = u8;
 
+pub type TeamId = u8;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PickupKind {
+    Food = 0,
+    SpeedBoost = 1,
+    Ghost = 2,
+    Shield = 3,
+    Shrink = 4
+}
+
 pub enum UpdateResult {
     Nothing,
     WallCollision,
@@ -25,6 +41,17 @@ pub enum StreamId {
     Move = 2
 }
 
+// https://amethyst.github.io/laminar/docs/reliability/reliability.html
+#[derive(Copy, Clone, PartialEq)]
+pub enum Delivery {
+    // Arrives once and in order, later packets wait for a lost one to be resent
+    ReliableOrdered,
+    // May be lost, and a packet older than one already received is dropped
+    UnreliableSequenced,
+    // May be lost or arrive out of order
+    Unreliable
+}
+
 #[derive(Copy, Clone)]
 pub enum GameResult {
     Win = 0,
@@ -35,16 +62,43 @@ pub enum GameResult {
 // All packets are prepended by [magic_byte, message_type]
 #[derive(Copy, Clone, PartialEq)]
 pub enum MessageType {
-    JoinEvent = 0,         // []
+    JoinEvent = 0,         // [r, g, b, name...]
     AssignIdEvent = 1,     // [assigned_id]
-    BroadcastIdsEvent = 2, // [id_1, id_2, ...]
-    StartEvent = 3,        // []
-    MoveEvent = 4,         // server: [id, move], client: [id_1, move_1, id_2, move_2, ...]
+    BroadcastIdsEvent = 2, // [id_1, r_1, g_1, b_1, name_length_1, name_1..., id_2, ...]
+    StartEvent = 3,        // [settings: MatchSettings]
+    MoveEvent = 4,         // server: [id, move, confirmed_tick (4 bytes)], client: [first_tick (4 bytes), move_count_1, id_1, move_1, ..., move_count_2, ...] of the unconfirmed ticks
     DeathEvent = 5,        // [id_alive_1, id_alive_2, ...]
-    EndEvent = 6,          // [result: GameResult, id_winner]
-    Heartbeat = 7          // []
+    EndEvent = 6,          // [result: GameResult, id_winner, team_winner]
+    Heartbeat = 7,         // client: [client_time (8 bytes), sequence (4 bytes), ping_millis (2 bytes, 65535 if unknown)], server: [client_time (8 bytes)]
+    TimeEvent = 8,         // [remaining_seconds (2 bytes)], followed by the last tick (4 bytes) when 0
+    ScoreEvent = 9,        // [id_1, length_1 (2 bytes), grown_tick_1 (4 bytes), id_2, ...]
+    LobbyEvent = 10,       // [name_length_1, name_1..., name_length_2, ...]
+    LeaveEvent = 11,       // []
+    CountdownEvent = 12    // [seconds_left], 0 when the first tick is sent
+}
+
+impl MessageType {
+    pub fn delivery(&self) -> (Delivery, StreamId) {
+        // Get how a message is sent. Tick data goes unreliably so a lost packet
+        // never holds up the next tick, which resends it anyway. Heartbeats
+        // only measure the ping and keep idle connections open
+        match self {
+            MessageType::MoveEvent => (Delivery::UnreliableSequenced, StreamId::Move),
+            MessageType::Heartbeat => (Delivery::Unreliable, StreamId::Heartbeat),
+            _ => (Delivery::ReliableOrdered, StreamId::Event)
+        }
+    }
 }
 
-pub const MAX_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 4;
 pub const INVALID_ID: SnekId = 0;
-pub const MAGIC_BYTE: u8 = 42;
\ No newline at end of file
+pub const NO_TEAM: TeamId = 0;
+pub const MAGIC_BYTE: u8 = 42;
+pub const MAX_NAME_LENGTH: usize = 16;
+pub const DEFAULT_COLOR: [u8; 3] = [230, 41, 55];
+pub const COUNTDOWN_SECONDS: u8 = 3;
+pub const STARTING_LENGTH: i32 = 10;
+pub const TRON_STARTING_LENGTH: i32 = 3;
+// Fewest empty cells in front of a spawned snek, to a wall or to a snek
+// spawned facing it, so every player has time to turn
+pub const SPAWN_GAP: i32 = 10;
\ No newline at end of file
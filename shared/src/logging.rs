@@ -0,0 +1,70 @@
+use std::env;
+use std::io::Write;
+use env_logger::Builder;
+use log::kv::{self, Key, Value, VisitSource};
+
+// Levels to log at, in env_logger's syntax such as "info,snek_server::server=debug"
+pub const LOG_ENV: &str = "SNEK_LOG";
+// Set to "json" to log JSON lines instead of text
+pub const LOG_FORMAT_ENV: &str = "SNEK_LOG_FORMAT";
+const DEFAULT_FILTER: &str = "info,laminar=warn";
+
+pub fn init(filter: Option<&str>, json: bool) {
+    // Start logging to stderr. The flags of the binary win over the
+    // environment, which wins over logging everything at the info level
+    let filter = filter.map(str::to_owned)
+        .or(env::var(LOG_ENV).ok())
+        .unwrap_or(DEFAULT_FILTER.to_owned());
+    let json = json || env::var(LOG_FORMAT_ENV).is_ok_and(|format| format.eq_ignore_ascii_case("json"));
+
+    let mut builder = Builder::new();
+    builder.parse_filters(&filter);
+    if json {
+        // One object per line, the key-values of a record become fields
+        builder.format(|buf, record| {
+            let mut line = format!("{{\"time\":\"{}\",\"level\":\"{}\",\"target\":{},\"message\":{}",
+                buf.timestamp_millis(), record.level(), json_string(record.target()), json_string(&record.args().to_string()));
+            let _ = record.key_values().visit(&mut JsonFields(&mut line));
+            line.push('}');
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+struct JsonFields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        // Numbers and booleans are written as they are, anything else as a string
+        let literal = value.to_i64().map(|number| number.to_string())
+            .or(value.to_u64().map(|number| number.to_string()))
+            .or(value.to_f64().filter(|number| number.is_finite()).map(|number| number.to_string()))
+            .or(value.to_bool().map(|boolean| boolean.to_string()))
+            .unwrap_or_else(|| json_string(&value.to_string()));
+        self.0.push(',');
+        self.0.push_str(&json_string(key.as_str()));
+        self.0.push(':');
+        self.0.push_str(&literal);
+        Ok(())
+    }
+}
+
+fn json_string(text: &str) -> String {
+    // Quote and escape a string for JSON
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if (character as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
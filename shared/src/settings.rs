@@ -0,0 +1,262 @@
+use crate::{SnekId, TeamId, INVALID_ID, NO_TEAM, SPAWN_GAP, STARTING_LENGTH, TRON_STARTING_LENGTH};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum GameMode {
+    Classic = 0,
+    // Tails never shrink, leaving permanent trails behind every snek
+    Tron = 1
+}
+
+impl GameMode {
+    pub fn from_u8(value: u8) -> GameMode {
+        // Get the game mode sent over the network
+        match value {
+            x if x == GameMode::Tron as u8 => GameMode::Tron,
+            _ => GameMode::Classic
+        }
+    }
+
+    pub fn default_tick_millis(&self) -> u16 {
+        // Get the default time between simulation ticks
+        match self {
+            GameMode::Classic => 120,
+            GameMode::Tron => 70
+        }
+    }
+}
+
+// Sent with the StartEvent so every client runs the same simulation
+#[derive(Copy, Clone)]
+pub struct MatchSettings {
+    pub mode: GameMode,
+    pub seed: u64,
+    pub power_ups: bool,
+    pub player_count: u8,
+    pub team_count: u8,
+    pub friendly_fire: bool,
+    pub duration: u16,
+    pub board_width: u8,
+    pub board_height: u8,
+    pub tick_millis: u16
+}
+
+impl Default for MatchSettings {
+    fn default() -> MatchSettings {
+        MatchSettings {
+            mode: GameMode::Classic, seed: 0, power_ups: true, player_count: 2, team_count: 0, friendly_fire: false,
+            duration: 0, board_width: 35, board_height: 35, tick_millis: GameMode::Classic.default_tick_millis()
+        }
+    }
+}
+
+impl MatchSettings {
+    pub fn encode(&self) -> Vec<u8> {
+        // Serialize the settings as [mode, power_ups, player_count, team_count,
+        // friendly_fire, board_width, board_height, tick_millis (2 bytes),
+        // duration (2 bytes), seed (8 bytes)]
+        let mut payload = vec![
+            self.mode as u8, self.power_ups as u8, self.player_count, self.team_count, self.friendly_fire as u8,
+            self.board_width, self.board_height
+        ];
+        payload.extend(self.tick_millis.to_be_bytes().iter());
+        payload.extend(self.duration.to_be_bytes().iter());
+        payload.extend(self.seed.to_be_bytes().iter());
+        payload
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<MatchSettings> {
+        // Deserialize the settings, None if the payload is too short
+        if payload.len() < 19 {
+            return None;
+        }
+        let mut seed = [0; 8];
+        seed.copy_from_slice(&payload[11..19]);
+        Some(MatchSettings {
+            mode: GameMode::from_u8(payload[0]),
+            seed: u64::from_be_bytes(seed),
+            power_ups: payload[1] != 0,
+            player_count: payload[2],
+            team_count: payload[3],
+            friendly_fire: payload[4] != 0,
+            board_width: payload[5],
+            board_height: payload[6],
+            tick_millis: u16::from_be_bytes([payload[7], payload[8]]),
+            duration: u16::from_be_bytes([payload[9], payload[10]])
+        })
+    }
+
+    pub fn min_board_size(&self) -> (u8, u8) {
+        // Get the smallest board the spawn layout fits on. Classic sneks line
+        // the top and bottom rows, and from the third player on they face each
+        // other along a row. Tron sneks face each other from the middle of
+        // each side, the third and fourth along a column
+        let (width, height) = match self.mode {
+            GameMode::Classic if self.player_count > 2 => (2 * STARTING_LENGTH + SPAWN_GAP, 10),
+            GameMode::Classic => (STARTING_LENGTH + SPAWN_GAP, 10),
+            GameMode::Tron if self.player_count > 2 => (2 * TRON_STARTING_LENGTH + 2 + SPAWN_GAP, 2 * TRON_STARTING_LENGTH + 2 + SPAWN_GAP),
+            GameMode::Tron => (2 * TRON_STARTING_LENGTH + 2 + SPAWN_GAP, 10)
+        };
+        (width.max(20) as u8, height as u8)
+    }
+
+    pub fn is_timed(&self) -> bool {
+        // Check if the match ends after a fixed duration
+        self.duration > 0
+    }
+
+    pub fn is_team_mode(&self) -> bool {
+        // Check if sneks play in teams instead of free-for-all
+        self.team_count > 0
+    }
+
+    pub fn team_of(&self, id: SnekId) -> TeamId {
+        // Get the team of a snek, ids are dealt out to the teams in turn
+        if !self.is_team_mode() || id == 0 {
+            return NO_TEAM;
+        }
+        (id - 1) % self.team_count + 1
+    }
+
+    pub fn are_teammates(&self, a: SnekId, b: SnekId) -> bool {
+        // Check if two different sneks play on the same team
+        a != b && self.team_of(a) != NO_TEAM && self.team_of(a) == self.team_of(b)
+    }
+
+    pub fn get_winner(&self, alive: &[SnekId]) -> Option<(SnekId, TeamId)> {
+        // Get the winning snek or team once a single snek, or a single team,
+        // is left. Nobody left is a tie
+        if alive.is_empty() {
+            Some((INVALID_ID, NO_TEAM))
+        } else if self.is_team_mode() && alive.iter().all(|&id| self.team_of(id) == self.team_of(alive[0])) {
+            Some((INVALID_ID, self.team_of(alive[0])))
+        } else if alive.len() == 1 {
+            Some((alive[0], NO_TEAM))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_timed_winner(&self, scores: &[u8]) -> (SnekId, TeamId) {
+        // Rank the sneks (or teams) from the standings in a ScoreEvent when the
+        // time ran out. Longer wins, then whoever reached their length first.
+        // Anything else is a tie
+        let mut standings: Vec<(u8, u32, u32)> = Vec::new();
+        for entry in scores.chunks_exact(7) {
+            let length = u16::from_be_bytes([entry[1], entry[2]]) as u32;
+            let grown_tick = u32::from_be_bytes([entry[3], entry[4], entry[5], entry[6]]);
+            let key = if self.is_team_mode() { self.team_of(entry[0]) } else { entry[0] };
+            match standings.iter_mut().find(|standing| standing.0 == key) {
+                Some(standing) => {
+                    standing.1 += length;
+                    standing.2 = standing.2.max(grown_tick);
+                },
+                None => standings.push((key, length, grown_tick))
+            }
+        }
+        standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+
+        if standings.is_empty() || (standings.len() > 1
+            && standings[0].1 == standings[1].1 && standings[0].2 == standings[1].2) {
+            (INVALID_ID, NO_TEAM)
+        } else if self.is_team_mode() {
+            (INVALID_ID, standings[0].0)
+        } else {
+            (standings[0].0, NO_TEAM)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn teams(team_count: u8) -> MatchSettings {
+        MatchSettings { player_count: 4, team_count, ..MatchSettings::default() }
+    }
+
+    #[test]
+    fn settings_survive_encoding() {
+        let settings = MatchSettings {
+            mode: GameMode::Tron, seed: 0x0123_4567_89ab_cdef, power_ups: false, player_count: 4, team_count: 2,
+            friendly_fire: true, duration: 300, board_width: 60, board_height: 40, tick_millis: 70
+        };
+        let decoded = MatchSettings::decode(&settings.encode()).unwrap();
+        assert!(decoded.mode == GameMode::Tron);
+        assert_eq!(decoded.seed, settings.seed);
+        assert!(!decoded.power_ups);
+        assert_eq!(decoded.player_count, 4);
+        assert_eq!(decoded.team_count, 2);
+        assert!(decoded.friendly_fire);
+        assert_eq!(decoded.duration, 300);
+        assert_eq!((decoded.board_width, decoded.board_height), (60, 40));
+        assert_eq!(decoded.tick_millis, 70);
+    }
+
+    #[test]
+    fn short_settings_are_rejected() {
+        let payload = MatchSettings::default().encode();
+        assert!(MatchSettings::decode(&payload[..payload.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn teams_are_dealt_out_in_turn() {
+        let settings = teams(2);
+        assert_eq!(settings.team_of(1), 1);
+        assert_eq!(settings.team_of(2), 2);
+        assert_eq!(settings.team_of(3), 1);
+        assert!(settings.are_teammates(1, 3));
+        assert!(!settings.are_teammates(1, 2));
+        assert!(!settings.are_teammates(1, 1));
+        assert_eq!(teams(0).team_of(1), NO_TEAM);
+        assert!(!teams(0).are_teammates(1, 2));
+    }
+
+    #[test]
+    fn the_last_snek_or_team_standing_wins() {
+        assert_eq!(teams(0).get_winner(&[1, 2]), None);
+        assert_eq!(teams(0).get_winner(&[2]), Some((2, NO_TEAM)));
+        assert_eq!(teams(0).get_winner(&[]), Some((INVALID_ID, NO_TEAM)));
+        assert_eq!(teams(2).get_winner(&[1, 3]), Some((INVALID_ID, 1)));
+        assert_eq!(teams(2).get_winner(&[1, 2]), None);
+    }
+
+    fn score(id: SnekId, length: u16, grown_tick: u32) -> Vec<u8> {
+        let mut entry = vec![id];
+        entry.extend(length.to_be_bytes().iter());
+        entry.extend(grown_tick.to_be_bytes().iter());
+        entry
+    }
+
+    #[test]
+    fn the_longest_snek_wins_when_time_runs_out() {
+        let scores = [score(1, 12, 40), score(2, 15, 90), score(3, 9, 10)].concat();
+        assert_eq!(teams(0).get_timed_winner(&scores), (2, NO_TEAM));
+    }
+
+    #[test]
+    fn equal_lengths_go_to_whoever_got_there_first() {
+        let scores = [score(1, 12, 40), score(2, 12, 30)].concat();
+        assert_eq!(teams(0).get_timed_winner(&scores), (2, NO_TEAM));
+    }
+
+    #[test]
+    fn equal_lengths_reached_together_tie() {
+        let scores = [score(1, 12, 30), score(2, 12, 30)].concat();
+        assert_eq!(teams(0).get_timed_winner(&scores), (INVALID_ID, NO_TEAM));
+        assert_eq!(teams(0).get_timed_winner(&[]), (INVALID_ID, NO_TEAM));
+    }
+
+    #[test]
+    fn teams_add_up_their_lengths() {
+        // Team 1 has sneks 1 and 3, team 2 has sneks 2 and 4
+        let scores = [score(1, 20, 50), score(2, 15, 10), score(3, 4, 20), score(4, 8, 30)].concat();
+        assert_eq!(teams(2).get_timed_winner(&scores), (INVALID_ID, 1));
+    }
+
+    #[test]
+    fn a_truncated_score_is_left_out() {
+        let mut scores = [score(1, 12, 40), score(2, 10, 30)].concat();
+        scores.extend([3, 0, 99]);
+        assert_eq!(teams(0).get_timed_winner(&scores), (1, NO_TEAM));
+    }
+}